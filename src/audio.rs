@@ -1,4 +1,4 @@
-use bevy::{prelude::*, utils::Uuid};
+use bevy::prelude::*;
 use bevy_kira_audio::{
     AudioPlugin as KiraAudioPlugin,
     AudioStreamPlugin,
@@ -23,7 +23,11 @@ pub enum Trigger {
     CharacterJump,
     CharacterAttack,
 	CharacterHit,
+	ShieldBreak,
 	WallBreak,
+	BouncyWallBreak,
+	ExplosiveWallBreak,
+	RegeneratingWallBreak,
 }
 
 #[derive(Debug)]
@@ -90,7 +94,6 @@ pub fn handle_audio_event(
     mut channels: ResMut<AudioChannelsBuffer>,
     audio: Res<StreamedAudio<OutputStream>>,
 ) {
-    let tag = Uuid::new_v4();
     if events.iter().count() + channels.0.len() > 40 {
         info!("{}, {}", events.iter().count(), channels.0.len());
         return
@@ -111,14 +114,27 @@ pub fn handle_audio_event(
                 Trigger::CharacterHit => {
                     build_synth_stream(hz.into(), 0.6).collect()
                 }
+                Trigger::ShieldBreak => {
+                    build_synth_stream((hz / 4.).into(), 0.7).collect()
+                }
                 Trigger::WallBreak => {
                     build_synth_stream(hz.into(), 0.4).collect()
                 }
+                Trigger::BouncyWallBreak => {
+                    build_synth_stream((hz * 2.).into(), 0.35).collect()
+                }
+                Trigger::ExplosiveWallBreak => {
+                    build_synth_stream((hz / 2.).into(), 0.9).collect()
+                }
+                Trigger::RegeneratingWallBreak => {
+                    build_synth_stream((hz * 1.5).into(), 0.25).collect()
+                }
             }
         })
         .collect();
     for synth in streams {
         let output = OutputStream(synth);
+        let tag = channels.next_tag();
         channels.0.push((tag, 2.));
         audio.stream_in_channel(
             output,
@@ -127,16 +143,24 @@ pub fn handle_audio_event(
     }
 }
 
+// channels are tagged from a counter so that nothing here touches an entropy source
 #[derive(Default)]
-pub struct AudioChannelsBuffer(Vec<(Uuid, f64)>);
+pub struct AudioChannelsBuffer(Vec<(u64, f64)>, u64);
+
+impl AudioChannelsBuffer {
+    fn next_tag(&mut self) -> u64 {
+        self.1 = self.1.wrapping_add(1);
+        self.1
+    }
+}
 
 pub struct AudioPlugin;
 
 impl Plugin for AudioPlugin {
     fn build(&self, app: &mut App) {
         app
+            // the `Event` channel itself is registered by the game simulation
             .init_resource::<AudioChannelsBuffer>()
-            .add_event::<Event>()
             .add_plugin(KiraAudioPlugin)
             .add_plugin(AudioStreamPlugin::<OutputStream>::default())
             .add_system(handle_audio_event)
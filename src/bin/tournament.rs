@@ -0,0 +1,646 @@
+use anyhow::{anyhow, Context};
+use bevy::{asset::AssetPlugin, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::BTreeMap,
+    fmt,
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use ballpit_arena_game::{
+    game::{
+        clock::{self, ClockMode, SimulationClock},
+        input::{self, ActionType, Trigger},
+        level::{ActiveLevel, BrickType, Durability, LevelSelection, DEFAULT_LEVEL},
+        player::{
+            ai::{AiPlayer, AiSettings, AiSettingsOverride, Behavior, Difficulty, FlockingSettings, Reaction, Role},
+            Activity,
+            Eliminations,
+            Enemy,
+            Player,
+            PlayerCount,
+            MAX_PLAYERS,
+            TIME_STEP,
+        },
+        rng::MatchSeed,
+        scoreboard::Score,
+        SimulationPlugin,
+    },
+    AppState,
+};
+
+// Plays enemies of every difficulty against scripted players, many seeds each,
+// without a window and as fast as the simulation can step. Every difficulty
+// plays the same seeds against the same players, so the difficulties can be
+// ranked against each other as well as against each baseline. The AI can also
+// take the player slots, so that its settings play each other directly.
+
+/// A scripted way of playing every player slot in a match.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Baseline {
+    /// never moves or presses anything
+    Idle,
+    /// walks at the nearest enemy and keeps attacking it
+    Masher,
+    /// keeps away from the enemies and shields whatever comes close
+    Runner,
+}
+
+impl FromStr for Baseline {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "idle" => Ok(Baseline::Idle),
+            "masher" => Ok(Baseline::Masher),
+            "runner" => Ok(Baseline::Runner),
+            _ => Err(format!("unknown baseline {}", name)),
+        }
+    }
+}
+
+/// What plays the player slots: a baseline, or the AI with one of the configs.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Players {
+    Baseline(Baseline),
+    Ai(String),
+}
+
+impl Players {
+    fn ai_config(&self) -> Option<&String> {
+        match self {
+            Players::Ai(config) => Some(config),
+            Players::Baseline(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Players {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Players::Baseline(baseline) => write!(f, "{:?}", baseline),
+            Players::Ai(config) => write!(f, "{} AI", config),
+        }
+    }
+}
+
+// steps between the masher's presses
+const MASH_INTERVAL: u64 = 15;
+// how close an enemy gets before a baseline attacks it or gets out of its way
+const ATTACK_RANGE: f32 = 100.;
+const DEFEND_RANGE: f32 = 90.;
+// how long a match may take to load its level and moves
+const LOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Plays every player slot the way the baseline does, through the same input
+/// events a device would send.
+fn drive_players(
+    baseline: Option<Res<Baseline>>,
+    clock: Res<SimulationClock>,
+    level: Res<ActiveLevel>,
+    player_query: Query<(&Player, &Transform), Without<Enemy>>,
+    enemy_query: Query<(&Transform, &Activity), With<Enemy>>,
+    mut events: EventWriter<input::Event>,
+) {
+    let baseline = match baseline {
+        Some(baseline) => baseline,
+        None => return,
+    };
+    let center = match level.definition.as_ref() {
+        Some(definition) => (definition.arena.min + definition.arena.max) / 2.,
+        None => return,
+    };
+    for (player, transform) in player_query.iter() {
+        let position = transform.translation.truncate();
+        let to_center = (center - position).normalize_or_zero();
+        let nearest = enemy_query.iter()
+            .map(|(enemy, activity)| (enemy.translation.truncate() - position, activity))
+            .min_by(|(a, _), (b, _)| a.length_squared().partial_cmp(&b.length_squared()).unwrap_or(Ordering::Equal));
+        // each slot presses on its own beat, so players don't all act at once
+        let beat = clock.tick + player.0 as u64 * 7;
+
+        let (movement, action) = match (*baseline, nearest) {
+            (Baseline::Idle, _) => (Vec2::ZERO, None),
+            // with nobody to go after, wait in the middle of the arena
+            (_, None) => (to_center * 0.5, None),
+            (Baseline::Masher, Some((to_enemy, _))) => {
+                let action = if beat % MASH_INTERVAL != 0 {
+                    None
+                } else if to_enemy.y > 70. {
+                    Some(ActionType::Jump)
+                } else if to_enemy.length() < ATTACK_RANGE {
+                    Some(if beat % (MASH_INTERVAL * 3) == 0 { ActionType::Stomp } else { ActionType::Jab })
+                } else {
+                    None
+                };
+                (to_enemy.normalize_or_zero(), action)
+            }
+            (Baseline::Runner, Some((to_enemy, activity))) => {
+                // leaning towards the middle keeps it from backing out through the walls
+                let movement = (to_center - to_enemy.normalize_or_zero()).normalize_or_zero();
+                let action = if matches!(activity, Activity::Attack(_)) && to_enemy.length() < DEFEND_RANGE {
+                    Some(ActionType::Shield)
+                } else if to_enemy.length() < DEFEND_RANGE && beat % (MASH_INTERVAL * 2) == 0 {
+                    Some(ActionType::Jump)
+                } else {
+                    None
+                };
+                (movement, action)
+            }
+        };
+        events.send(input::Event(player.0, Trigger::PlayerMovement(movement.x, movement.y)));
+        if let Some(action) = action {
+            events.send(input::Event(player.0, Trigger::PlayerAction(action)));
+        }
+    }
+}
+
+/// The settings the AI plays every player slot with, instead of a baseline.
+struct AiPlayers(AiSettings);
+
+/// Hands every player slot to the AI as it spawns, which from then on plays
+/// it the way enemies are played.
+fn attach_ai_players(
+    mut commands: Commands,
+    ai_players: Option<Res<AiPlayers>>,
+    player_query: Query<Entity, Added<Player>>,
+) {
+    if let Some(ai_players) = ai_players {
+        for player in player_query.iter() {
+            commands.entity(player)
+                .insert(AiPlayer(ai_players.0))
+                .insert(Behavior::Chasing)
+                .insert(Reaction::default())
+                .insert(Role::Pressure);
+        }
+    }
+}
+
+/// Durability taken off bricks over the match, counting the rest of a brick's
+/// durability once it breaks.
+#[derive(Default)]
+struct WallDamage {
+    total: f32,
+    durability: HashMap<Entity, f32>,
+}
+
+fn track_wall_damage(
+    mut wall_damage: ResMut<WallDamage>,
+    brick_query: Query<(Entity, &Durability), With<BrickType>>,
+) {
+    let mut durability = HashMap::default();
+    for (brick, current) in brick_query.iter() {
+        let current = current.0.max(0.);
+        let previous = wall_damage.durability.get(&brick).copied().unwrap_or(current);
+        wall_damage.total += (previous - current).max(0.);
+        durability.insert(brick, current);
+    }
+    // bricks that are gone, or regrowing, broke with whatever they had left
+    let broken: f32 = wall_damage.durability.iter()
+        .filter(|(brick, _)| !durability.contains_key(*brick))
+        .map(|(_, previous)| *previous)
+        .sum();
+    wall_damage.total += broken;
+    wall_damage.durability = durability;
+}
+
+/// AI settings to play with besides the difficulties', read from the RON file
+/// given with `--tuning`, e.g.
+/// `(configs: {"twitchy": (reaction_delay: 2, mistake_rate: 0.2, aggression: 1.8)}, flocking: (separation: 2.))`
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Tuning {
+    /// settings to pick by name, replacing the difficulty of the same name if there is one
+    configs: BTreeMap<String, AiSettings>,
+    /// how every AI in the tournament flocks, with the defaults for anything left out
+    flocking: FlockingSettings,
+}
+
+struct Options {
+    matches: u64,
+    seed: u64,
+    minutes: f32,
+    players: usize,
+    level: String,
+    /// what plays the enemies, by config name
+    difficulties: Vec<String>,
+    /// what plays the player slots
+    sides: Vec<Players>,
+    /// the settings of every config that can be picked, by name
+    configs: BTreeMap<String, AiSettings>,
+    flocking: FlockingSettings,
+    out: PathBuf,
+}
+
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
+fn parse_arg<T: FromStr>(name: &str, default: T) -> anyhow::Result<T> {
+    match arg_value(name) {
+        Some(value) => value.parse().map_err(|_| anyhow!("{} can't be {}", name, value)),
+        None => Ok(default),
+    }
+}
+
+fn parse_list<T: FromStr>(name: &str, default: Vec<T>) -> anyhow::Result<Vec<T>>
+where
+    T::Err: fmt::Display,
+{
+    match arg_value(name) {
+        Some(value) => value.split(',').map(|item| item.trim().parse().map_err(|error| anyhow!("{}", error))).collect(),
+        None => Ok(default),
+    }
+}
+
+impl Options {
+    // `--matches <number>` seeds per pairing, counting up from `--seed <number>`,
+    // `--minutes <number>` of match time before the players are given the win,
+    // `--players <1-4>` in every match, `--level <file>` to play on,
+    // `--difficulties easy,normal,hard` for the enemies to pair up with the players,
+    // `--baselines idle,masher,runner` and `--players-ai easy,normal,hard` (only the AI
+    // plays the players when `--players-ai` is given without `--baselines`),
+    // `--tuning <file>` for AI configs to pick by name alongside the difficulties,
+    // `--out <path>` for the results, written to `<path>.csv`, `<path>-versus.csv`,
+    // `<path>-head-to-head.csv` and `<path>.json`
+    fn from_args() -> anyhow::Result<Self> {
+        let tuning: Tuning = match arg_value("--tuning") {
+            Some(path) => {
+                let contents = fs::read_to_string(&path).with_context(|| format!("could not read {}", path))?;
+                ron::from_str(&contents).with_context(|| format!("could not parse {}", path))?
+            }
+            None => Tuning::default(),
+        };
+        let mut configs: BTreeMap<String, AiSettings> = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard]
+            .iter()
+            .map(|difficulty| (format!("{:?}", difficulty).to_lowercase(), difficulty.settings()))
+            .collect();
+        configs.extend(tuning.configs);
+
+        let players_ai: Vec<String> = parse_list("--players-ai", Vec::new())?;
+        let default_baselines = if players_ai.is_empty() {
+            vec![Baseline::Idle, Baseline::Masher, Baseline::Runner]
+        } else {
+            Vec::new()
+        };
+        let baselines: Vec<Baseline> = parse_list("--baselines", default_baselines)?;
+        let options = Options {
+            matches: parse_arg("--matches", 10)?,
+            seed: parse_arg("--seed", 0)?,
+            minutes: parse_arg("--minutes", 3.)?,
+            players: parse_arg("--players", 1)?,
+            level: arg_value("--level").unwrap_or_else(|| DEFAULT_LEVEL.to_string()),
+            difficulties: parse_list("--difficulties", vec!["easy".to_string(), "normal".to_string(), "hard".to_string()])?,
+            sides: baselines.into_iter().map(Players::Baseline)
+                .chain(players_ai.into_iter().map(Players::Ai))
+                .collect(),
+            configs,
+            flocking: tuning.flocking,
+            out: PathBuf::from(arg_value("--out").unwrap_or_else(|| "tournament".to_string())),
+        };
+        if options.players == 0 || options.players > MAX_PLAYERS {
+            return Err(anyhow!("--players must be between 1 and {}", MAX_PLAYERS))
+        }
+        let picked = options.sides.iter()
+            .filter_map(Players::ai_config)
+            .chain(options.difficulties.iter());
+        for config in picked {
+            if !options.configs.contains_key(config) {
+                return Err(anyhow!("unknown AI config {}, pick a difficulty or one from --tuning", config))
+            }
+        }
+        Ok(options)
+    }
+
+    fn settings(&self, config: &str) -> AiSettings {
+        self.configs[config]
+    }
+
+    fn step_limit(&self) -> u64 {
+        (self.minutes * 60. / TIME_STEP) as u64
+    }
+}
+
+#[derive(Serialize)]
+struct MatchResult {
+    /// the config the enemies played with
+    difficulty: String,
+    players: Players,
+    seed: u64,
+    /// every player was knocked out before time ran out
+    enemies_won: bool,
+    seconds: f32,
+    enemy_knockouts: i32,
+    player_knockouts: usize,
+    wall_damage: f32,
+}
+
+impl MatchResult {
+    fn knockouts(&self) -> f32 {
+        self.enemy_knockouts as f32 + self.player_knockouts as f32
+    }
+}
+
+fn play_match(options: &Options, difficulty: &str, players: &Players, seed: u64) -> anyhow::Result<MatchResult> {
+    let mut app = App::new();
+    app.insert_resource(MatchSeed(seed))
+        .insert_resource(LevelSelection(options.level.clone()))
+        .insert_resource(PlayerCount(options.players))
+        .insert_resource(AiSettingsOverride(Some(options.settings(difficulty))))
+        .insert_resource(options.flocking)
+        .insert_resource(SimulationClock::new(ClockMode::Uncapped))
+        .init_resource::<WallDamage>();
+    match players {
+        Players::Baseline(baseline) => app.insert_resource(*baseline),
+        Players::Ai(config) => app.insert_resource(AiPlayers(options.settings(config))),
+    };
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_state(AppState::Game)
+        .add_plugin(SimulationPlugin)
+        .add_system_to_stage(CoreStage::PreUpdate, attach_ai_players)
+        .add_system_set(
+            SystemSet::new()
+                .with_run_criteria(clock::SIMULATION_STEP)
+                .with_system(drive_players.label("read_input"))
+                .with_system(
+                    track_wall_damage
+                        .after("handle_brick_regrowth")
+                        .after("detect_gameover_system")
+                )
+        );
+
+    let started = Instant::now();
+    loop {
+        app.update();
+        let tick = app.world.get_resource::<SimulationClock>().unwrap().tick;
+        let state = app.world.get_resource::<State<AppState>>().unwrap();
+        if state.current() == &AppState::GameOver || tick >= options.step_limit() {
+            break
+        }
+        if tick == 0 && started.elapsed() > LOAD_TIMEOUT {
+            return Err(anyhow!("{} or the move set never loaded", options.level))
+        }
+    }
+
+    let world = &app.world;
+    Ok(MatchResult {
+        difficulty: difficulty.to_string(),
+        players: players.clone(),
+        seed,
+        enemies_won: world.get_resource::<State<AppState>>().unwrap().current() == &AppState::GameOver,
+        seconds: world.get_resource::<SimulationClock>().unwrap().elapsed_seconds(),
+        enemy_knockouts: world.get_resource::<Score>().unwrap().0,
+        player_knockouts: world.get_resource::<Eliminations>().unwrap().0.len(),
+        wall_damage: world.get_resource::<WallDamage>().unwrap().total,
+    })
+}
+
+/// How one difficulty did against one side over every seed.
+#[derive(Serialize)]
+struct Matchup {
+    difficulty: String,
+    players: Players,
+    matches: usize,
+    enemy_win_rate: f32,
+    player_win_rate: f32,
+    average_seconds: f32,
+    knockouts_per_minute: f32,
+    average_wall_damage: f32,
+}
+
+fn summarize(difficulty: &str, players: &Players, results: &[MatchResult]) -> Matchup {
+    let played: Vec<&MatchResult> = results.iter()
+        .filter(|result| result.difficulty == difficulty && result.players == *players)
+        .collect();
+    let matches = played.len().max(1) as f32;
+    let enemy_wins = played.iter().filter(|result| result.enemies_won).count() as f32;
+    let seconds: f32 = played.iter().map(|result| result.seconds).sum();
+    let knockouts: f32 = played.iter().map(|result| result.knockouts()).sum();
+    let wall_damage: f32 = played.iter().map(|result| result.wall_damage).sum();
+    Matchup {
+        difficulty: difficulty.to_string(),
+        players: players.clone(),
+        matches: played.len(),
+        enemy_win_rate: enemy_wins / matches,
+        player_win_rate: 1. - enemy_wins / matches,
+        average_seconds: seconds / matches,
+        knockouts_per_minute: if seconds > 0. { knockouts / (seconds / 60.) } else { 0. },
+        average_wall_damage: wall_damage / matches,
+    }
+}
+
+// how well the enemies did: winning beats losing and winning sooner beats
+// winning later; without a win, knocking out more players and losing fewer
+// enemies is better
+fn compare(a: &MatchResult, b: &MatchResult) -> Ordering {
+    match (a.enemies_won, b.enemies_won) {
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (true, true) => b.seconds.partial_cmp(&a.seconds).unwrap_or(Ordering::Equal),
+        (false, false) => a.player_knockouts.cmp(&b.player_knockouts)
+            .then(b.enemy_knockouts.cmp(&a.enemy_knockouts)),
+    }
+}
+
+/// How one difficulty did against another, over the matches both played
+/// against the same players and seed.
+#[derive(Serialize)]
+struct Versus {
+    difficulty: String,
+    opponent: String,
+    wins: usize,
+    losses: usize,
+    draws: usize,
+}
+
+fn compare_difficulties(difficulty: &str, opponent: &str, results: &[MatchResult]) -> Versus {
+    let mut versus = Versus {
+        difficulty: difficulty.to_string(),
+        opponent: opponent.to_string(),
+        wins: 0,
+        losses: 0,
+        draws: 0,
+    };
+    for result in results.iter().filter(|result| result.difficulty == difficulty) {
+        let other = results.iter().find(|other| {
+            other.difficulty == opponent && other.players == result.players && other.seed == result.seed
+        });
+        match other.map(|other| compare(result, other)) {
+            Some(Ordering::Greater) => versus.wins += 1,
+            Some(Ordering::Less) => versus.losses += 1,
+            Some(Ordering::Equal) => versus.draws += 1,
+            None => {}
+        }
+    }
+    versus
+}
+
+/// How one AI config did playing another directly, on either side: as the
+/// players it wins by lasting until time runs out, and as the enemies by
+/// knocking every player out.
+#[derive(Serialize)]
+struct HeadToHead {
+    config: String,
+    opponent: String,
+    matches: usize,
+    wins: usize,
+    losses: usize,
+}
+
+fn head_to_head(config: &str, opponent: &str, results: &[MatchResult]) -> HeadToHead {
+    let mut record = HeadToHead {
+        config: config.to_string(),
+        opponent: opponent.to_string(),
+        matches: 0,
+        wins: 0,
+        losses: 0,
+    };
+    for result in results.iter() {
+        let won = match &result.players {
+            Players::Ai(players) if players == config && result.difficulty == opponent => !result.enemies_won,
+            Players::Ai(players) if players == opponent && result.difficulty == config => result.enemies_won,
+            _ => continue,
+        };
+        record.matches += 1;
+        if won {
+            record.wins += 1;
+        } else {
+            record.losses += 1;
+        }
+    }
+    record
+}
+
+#[derive(Serialize)]
+struct Report {
+    /// the settings of every config that played, by name
+    configs: BTreeMap<String, AiSettings>,
+    flocking: FlockingSettings,
+    head_to_head: Vec<HeadToHead>,
+    matchups: Vec<Matchup>,
+    versus: Vec<Versus>,
+    matches: Vec<MatchResult>,
+}
+
+impl Report {
+    fn matchups_csv(&self) -> String {
+        let mut csv = String::from(
+            "difficulty,players,matches,enemy_win_rate,player_win_rate,average_seconds,knockouts_per_minute,average_wall_damage\n"
+        );
+        for matchup in self.matchups.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{:.3},{:.3},{:.2},{:.3},{:.1}\n",
+                matchup.difficulty,
+                matchup.players,
+                matchup.matches,
+                matchup.enemy_win_rate,
+                matchup.player_win_rate,
+                matchup.average_seconds,
+                matchup.knockouts_per_minute,
+                matchup.average_wall_damage,
+            ));
+        }
+        csv
+    }
+
+    fn versus_csv(&self) -> String {
+        let mut csv = String::from("difficulty,opponent,wins,losses,draws\n");
+        for versus in self.versus.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                versus.difficulty, versus.opponent, versus.wins, versus.losses, versus.draws,
+            ));
+        }
+        csv
+    }
+
+    fn head_to_head_csv(&self) -> String {
+        let mut csv = String::from("config,opponent,matches,wins,losses\n");
+        for record in self.head_to_head.iter() {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                record.config, record.opponent, record.matches, record.wins, record.losses,
+            ));
+        }
+        csv
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let options = Options::from_args()?;
+
+    let mut results = Vec::new();
+    for difficulty in options.difficulties.iter() {
+        for players in options.sides.iter() {
+            for seed in options.seed..options.seed + options.matches {
+                let result = play_match(&options, difficulty, players, seed)?;
+                println!(
+                    "{} vs {}, seed {}: {} after {:.1}s",
+                    difficulty,
+                    players,
+                    seed,
+                    if result.enemies_won { "enemies won" } else { "players survived" },
+                    result.seconds,
+                );
+                results.push(result);
+            }
+        }
+    }
+
+    let mut matchups = Vec::new();
+    let mut versus = Vec::new();
+    for difficulty in options.difficulties.iter() {
+        for players in options.sides.iter() {
+            matchups.push(summarize(difficulty, players, &results));
+        }
+        for opponent in options.difficulties.iter().filter(|opponent| *opponent != difficulty) {
+            versus.push(compare_difficulties(difficulty, opponent, &results));
+        }
+    }
+    // every config that played on the player side, against every config it faced
+    let mut played: Vec<&String> = options.difficulties.iter()
+        .chain(options.sides.iter().filter_map(Players::ai_config))
+        .collect();
+    played.sort();
+    played.dedup();
+    let head_to_head: Vec<HeadToHead> = played.iter()
+        .flat_map(|config| played.iter().map(move |opponent| (*config, *opponent)))
+        .filter(|(config, opponent)| config != opponent)
+        .map(|(config, opponent)| head_to_head(config, opponent, &results))
+        .filter(|record| record.matches > 0)
+        .collect();
+    let configs = played.iter()
+        .map(|config| ((*config).clone(), options.settings(config)))
+        .collect();
+    let report = Report { configs, flocking: options.flocking, head_to_head, matchups, versus, matches: results };
+
+    let output = |suffix: &str| {
+        let mut name = options.out.clone().into_os_string();
+        name.push(suffix);
+        PathBuf::from(name)
+    };
+    if let Some(parent) = options.out.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        fs::create_dir_all(parent).with_context(|| format!("could not create {}", parent.display()))?;
+    }
+    for (path, contents) in [
+        (output(".csv"), report.matchups_csv()),
+        (output("-versus.csv"), report.versus_csv()),
+        (output("-head-to-head.csv"), report.head_to_head_csv()),
+        (output(".json"), serde_json::to_string_pretty(&report)?),
+    ] {
+        fs::write(&path, contents).with_context(|| format!("could not write {}", path.display()))?;
+        println!("wrote {}", path.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::game::input::ActionType;
+
+// Which keys and buttons do what. Every control can have any number of
+// bindings on each kind of device, and they are saved as RON to the user's
+// config directory whenever they're changed from the pause menu.
+
+/// Something a key or button can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Control {
+    Up,
+    Down,
+    Left,
+    Right,
+    Jump,
+    Jab,
+    Stomp,
+    Counter,
+    Shield,
+    Dodge,
+    Grab,
+    Pause,
+    /// claims a slot on the join screen
+    Join,
+    /// gives the slot back
+    Leave,
+    /// begins the match from the join screen
+    Start,
+    /// picks the next enemy difficulty on the join screen
+    ChangeDifficulty,
+}
+
+impl Control {
+    /// every control, in the order the pause menu lists them
+    pub const ALL: [Control; 16] = [
+        Control::Up,
+        Control::Down,
+        Control::Left,
+        Control::Right,
+        Control::Jump,
+        Control::Jab,
+        Control::Stomp,
+        Control::Counter,
+        Control::Shield,
+        Control::Dodge,
+        Control::Grab,
+        Control::Pause,
+        Control::Join,
+        Control::Leave,
+        Control::Start,
+        Control::ChangeDifficulty,
+    ];
+
+    /// the action a character takes for this control, if it's not movement,
+    /// pause or one of the join screen's
+    pub fn action(&self) -> Option<ActionType> {
+        match self {
+            Control::Jump => Some(ActionType::Jump),
+            Control::Jab => Some(ActionType::Jab),
+            Control::Stomp => Some(ActionType::Stomp),
+            Control::Counter => Some(ActionType::Counter),
+            Control::Shield => Some(ActionType::Shield),
+            Control::Dodge => Some(ActionType::Dodge),
+            Control::Grab => Some(ActionType::Grab),
+            _ => None,
+        }
+    }
+}
+
+/// How the analog stick is read.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StickSettings {
+    /// how far the stick has to be pushed before it counts, from 0 to 1
+    pub deadzone: f32,
+    /// scales the stick past the deadzone; above 1, a partial push reads as a full one
+    pub sensitivity: f32,
+}
+
+impl StickSettings {
+    /// the stick position as the game sees it, no longer than 1
+    pub fn apply(&self, raw: Vec2) -> Vec2 {
+        let length = raw.length();
+        if length <= self.deadzone || self.deadzone >= 1. {
+            return Vec2::ZERO
+        }
+        // rescaled so the edge of the deadzone reads as 0 rather than jumping
+        let scaled = (length - self.deadzone) / (1. - self.deadzone) * self.sensitivity;
+        (raw / length * scaled).clamp_length_max(1.)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Bindings {
+    pub keyboard: BTreeMap<Control, Vec<KeyCode>>,
+    pub gamepad: BTreeMap<Control, Vec<GamepadButtonType>>,
+    pub stick: StickSettings,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let keyboard = [
+            (Control::Up, vec![KeyCode::W]),
+            (Control::Down, vec![KeyCode::S]),
+            (Control::Left, vec![KeyCode::A]),
+            (Control::Right, vec![KeyCode::D]),
+            (Control::Jump, vec![KeyCode::Space]),
+            (Control::Jab, vec![KeyCode::Q]),
+            (Control::Stomp, vec![KeyCode::E]),
+            (Control::Counter, vec![KeyCode::R]),
+            (Control::Shield, vec![KeyCode::F]),
+            (Control::Dodge, vec![KeyCode::LShift]),
+            (Control::Grab, vec![KeyCode::G]),
+            (Control::Pause, vec![KeyCode::Escape]),
+            (Control::Join, vec![KeyCode::Space]),
+            (Control::Leave, vec![KeyCode::Back]),
+            (Control::Start, vec![KeyCode::Return]),
+            (Control::ChangeDifficulty, vec![KeyCode::Tab]),
+        ];
+        let gamepad = [
+            (Control::Up, vec![GamepadButtonType::DPadUp]),
+            (Control::Down, vec![GamepadButtonType::DPadDown]),
+            (Control::Left, vec![GamepadButtonType::DPadLeft]),
+            (Control::Right, vec![GamepadButtonType::DPadRight]),
+            (Control::Jump, vec![GamepadButtonType::South]),
+            (Control::Jab, vec![GamepadButtonType::West]),
+            (Control::Stomp, vec![GamepadButtonType::East]),
+            (Control::Counter, vec![GamepadButtonType::North]),
+            (Control::Shield, vec![GamepadButtonType::RightTrigger2]),
+            (Control::Dodge, vec![GamepadButtonType::LeftTrigger2]),
+            (Control::Grab, vec![GamepadButtonType::RightTrigger]),
+            (Control::Pause, vec![GamepadButtonType::Start]),
+            (Control::Join, vec![GamepadButtonType::South]),
+            (Control::Leave, vec![GamepadButtonType::East]),
+            (Control::Start, vec![GamepadButtonType::Start]),
+            (Control::ChangeDifficulty, vec![GamepadButtonType::Select]),
+        ];
+        Bindings {
+            keyboard: keyboard.into_iter().collect(),
+            gamepad: gamepad.into_iter().collect(),
+            stick: StickSettings {
+                deadzone: 0.15,
+                sensitivity: 1.,
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(std::io::Error),
+    Format(ron::Error),
+}
+
+impl Bindings {
+    pub fn keys(&self, control: Control) -> &[KeyCode] {
+        self.keyboard.get(&control).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn buttons(&self, control: Control) -> &[GamepadButtonType] {
+        self.gamepad.get(&control).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn is_key_pressed(&self, control: Control, keys: &Input<KeyCode>) -> bool {
+        self.keys(control).iter().any(|key| keys.pressed(*key))
+    }
+
+    pub fn is_key_just_pressed(&self, control: Control, keys: &Input<KeyCode>) -> bool {
+        self.keys(control).iter().any(|key| keys.just_pressed(*key))
+    }
+
+    pub fn is_key_just_released(&self, control: Control, keys: &Input<KeyCode>) -> bool {
+        self.keys(control).iter().any(|key| keys.just_released(*key))
+    }
+
+    pub fn is_button_pressed(&self, control: Control, gamepad: Gamepad, buttons: &Input<GamepadButton>) -> bool {
+        self.buttons(control).iter().any(|button| buttons.pressed(GamepadButton(gamepad, *button)))
+    }
+
+    pub fn is_button_just_pressed(&self, control: Control, gamepad: Gamepad, buttons: &Input<GamepadButton>) -> bool {
+        self.buttons(control).iter().any(|button| buttons.just_pressed(GamepadButton(gamepad, *button)))
+    }
+
+    /// The first button and key bound to `control`, for on-screen prompts
+    /// like "press South / Space to join".
+    pub fn prompt(&self, control: Control) -> String {
+        let first_button = self.buttons(control).first().map(|button| format!("{:?}", button));
+        let first_key = self.keys(control).first().map(|key| format!("{:?}", key));
+        let names: Vec<String> = first_button.into_iter().chain(first_key).collect();
+        if names.is_empty() {
+            "(unbound)".to_string()
+        } else {
+            names.join(" / ")
+        }
+    }
+
+    /// Binds `key` to `control`, or unbinds it if it already was, so a control
+    /// can be given several keys one at a time.
+    pub fn toggle_key(&mut self, control: Control, key: KeyCode) {
+        let keys = self.keyboard.entry(control).or_default();
+        match keys.iter().position(|bound| *bound == key) {
+            Some(index) => { keys.remove(index); }
+            None => keys.push(key),
+        }
+    }
+
+    /// Binds `button` to `control`, or unbinds it if it already was.
+    pub fn toggle_button(&mut self, control: Control, button: GamepadButtonType) {
+        let buttons = self.gamepad.entry(control).or_default();
+        match buttons.iter().position(|bound| *bound == button) {
+            Some(index) => { buttons.remove(index); }
+            None => buttons.push(button),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, BindingsError> {
+        let contents = fs::read_to_string(path).map_err(BindingsError::Io)?;
+        let mut bindings: Bindings = ron::de::from_str(&contents).map_err(BindingsError::Format)?;
+        // controls added since the file was saved start out with their defaults
+        let defaults = Bindings::default();
+        for control in Control::ALL {
+            if !bindings.keyboard.contains_key(&control) {
+                bindings.keyboard.insert(control, defaults.keys(control).to_vec());
+            }
+            if !bindings.gamepad.contains_key(&control) {
+                bindings.gamepad.insert(control, defaults.buttons(control).to_vec());
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// The bindings saved at `path`, or the defaults if there aren't any yet
+    /// or they can't be read.
+    pub fn load_or_default(path: &Path) -> Self {
+        if !path.exists() {
+            return Bindings::default()
+        }
+        Bindings::load(path).unwrap_or_else(|error| {
+            warn!("could not read bindings from {}, using the defaults: {:?}", path.display(), error);
+            Bindings::default()
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), BindingsError> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(BindingsError::Format)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(BindingsError::Io)?;
+        }
+        fs::write(path, contents).map_err(BindingsError::Io)
+    }
+}
+
+/// Where bindings are saved once they've been changed. Without it, changes only
+/// last until the game is closed.
+pub struct BindingsFile(pub PathBuf);
+
+impl BindingsFile {
+    /// `bindings.ron` in the user's config directory, if the platform has one
+    pub fn in_user_config() -> Option<Self> {
+        dirs::config_dir().map(|dir| BindingsFile(dir.join("ballpit-arena").join("bindings.ron")))
+    }
+}
+
+pub fn save_bindings(
+    bindings: Res<Bindings>,
+    file: Option<Res<BindingsFile>>,
+) {
+    if !bindings.is_changed() {
+        return
+    }
+    if let Some(file) = file {
+        match bindings.save(&file.0) {
+            Ok(()) => info!("saved bindings to {}", file.0.display()),
+            Err(error) => warn!("failed to save bindings to {}: {:?}", file.0.display(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_saved_before_a_control_existed_get_its_defaults() {
+        let mut saved = Bindings::default();
+        for control in [Control::Join, Control::Leave, Control::Start, Control::ChangeDifficulty] {
+            saved.keyboard.remove(&control);
+            saved.gamepad.remove(&control);
+        }
+        saved.toggle_key(Control::Jump, KeyCode::Up);
+        saved.keyboard.insert(Control::Grab, Vec::new());
+        let path = std::env::temp_dir().join(format!("ballpit-arena-bindings-{}.ron", std::process::id()));
+        saved.save(&path).unwrap();
+        let loaded = Bindings::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.keys(Control::Join), &[KeyCode::Space]);
+        assert_eq!(loaded.buttons(Control::Start), &[GamepadButtonType::Start]);
+        // what was saved is kept, including controls left with no keys at all
+        assert_eq!(loaded.keys(Control::Jump), &[KeyCode::Space, KeyCode::Up]);
+        assert!(loaded.keys(Control::Grab).is_empty());
+    }
+}
@@ -0,0 +1,84 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+
+use crate::{AppState, game::{level::ActiveLevel, player::{moves::ActiveMoveSet, TIME_STEP}}};
+
+pub const SIMULATION_STEP: &str = "simulation_step";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockMode {
+    /// Steps are paced against `Time`, so the game runs at real speed.
+    Realtime,
+    /// One step per app update, as fast as the app can be updated.
+    Uncapped,
+}
+
+/// The one clock every gameplay system runs on.
+/// Systems that need elapsed time should use `TIME_STEP` rather than `Time`,
+/// and systems that need to know "when" should use `tick`.
+pub struct SimulationClock {
+    pub mode: ClockMode,
+    pub tick: u64,
+    accumulator: f64,
+    looping: bool,
+}
+
+impl SimulationClock {
+    pub fn new(mode: ClockMode) -> Self {
+        SimulationClock {
+            mode,
+            tick: 0,
+            accumulator: 0.,
+            looping: false,
+        }
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.tick as f32 * TIME_STEP
+    }
+}
+
+impl Default for SimulationClock {
+    fn default() -> Self {
+        SimulationClock::new(ClockMode::Realtime)
+    }
+}
+
+pub fn run_simulation_step(
+    mut clock: ResMut<SimulationClock>,
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    level: Res<ActiveLevel>,
+    move_set: Res<ActiveMoveSet>,
+) -> ShouldRun {
+    // the clock only starts once there is a level to play on and moves to play with
+    if state.current() != &AppState::Game || !level.is_spawned() || !move_set.is_loaded() {
+        clock.looping = false;
+        return ShouldRun::No
+    }
+    match clock.mode {
+        ClockMode::Realtime => {
+            if !clock.looping {
+                clock.accumulator += time.delta_seconds_f64();
+            }
+            if clock.accumulator >= TIME_STEP as f64 {
+                clock.accumulator -= TIME_STEP as f64;
+                clock.looping = true;
+                clock.tick += 1;
+                ShouldRun::YesAndCheckAgain
+            } else {
+                clock.looping = false;
+                ShouldRun::No
+            }
+        }
+        ClockMode::Uncapped => {
+            clock.tick += 1;
+            ShouldRun::Yes
+        }
+    }
+}
+
+pub fn reset_clock(mut clock: ResMut<SimulationClock>) {
+    clock.tick = 0;
+    clock.accumulator = 0.;
+    clock.looping = false;
+}
@@ -0,0 +1,194 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::game::{
+    clock::SimulationClock,
+    level::{solidify_brick, ActiveLevel, BrickGrid, BrickType, Cell, Durability, LevelDefinition},
+    player::TIME_STEP,
+};
+
+// The arena closes in on a schedule set by the level: walls are worn down over
+// time, and new rings of bricks appear inside the old ones. Every stage is
+// announced by a warning period first, so players can get out of the way.
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum CollapseAction {
+    /// wear down every brick in the arena for `duration` seconds
+    Drain { per_second: f32, duration: f32 },
+    /// close in with a ring of bricks `inset` cells inside the outermost bricks
+    Ring { inset: i32, kind: String },
+}
+
+fn default_warning() -> f32 {
+    3.
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CollapseStage {
+    /// seconds into the match the stage takes effect
+    pub at: f32,
+    /// seconds of warning given before it does
+    #[serde(default = "default_warning")]
+    pub warning: f32,
+    pub action: CollapseAction,
+}
+
+/// How the level's collapse schedule is applied; game modes tune this.
+pub struct CollapseSettings {
+    pub enabled: bool,
+    /// how much faster than scheduled the stages arrive
+    pub pace: f32,
+    /// multiplies the durability lost to draining stages
+    pub drain_scale: f32,
+}
+
+impl Default for CollapseSettings {
+    fn default() -> Self {
+        CollapseSettings {
+            enabled: true,
+            pace: 1.,
+            drain_scale: 1.,
+        }
+    }
+}
+
+/// Marks bricks that are about to be affected by a collapse stage.
+#[derive(Component)]
+pub struct CollapseWarning(pub usize);
+
+/// A brick from a ring that has been announced but can't be collided with or
+/// damaged yet.
+#[derive(Component)]
+pub struct PendingBrick;
+
+struct ActiveDrain {
+    per_second: f32,
+    remaining: f32,
+}
+
+#[derive(Default)]
+pub struct CollapseDirector {
+    next_warning: usize,
+    next_stage: usize,
+    drains: Vec<ActiveDrain>,
+}
+
+pub fn reset_director(mut director: ResMut<CollapseDirector>) {
+    *director = CollapseDirector::default();
+}
+
+// cells of the rectangle `inset` cells inside the level's outermost bricks
+fn ring_cells(definition: &LevelDefinition, inset: i32) -> Vec<Cell> {
+    let cells = definition.brick_cells();
+    if cells.is_empty() {
+        return Vec::new()
+    }
+    let (min, max) = cells.iter().fold(
+        ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN)),
+        |(min, max), ((x, y), _)| ((min.0.min(*x), min.1.min(*y)), (max.0.max(*x), max.1.max(*y))),
+    );
+    let (min, max) = ((min.0 + inset, min.1 + inset), (max.0 - inset, max.1 - inset));
+    if min.0 >= max.0 || min.1 >= max.1 {
+        return Vec::new()
+    }
+    let horizontal = (min.0..=max.0).flat_map(|x| vec![(x, min.1), (x, max.1)]);
+    let vertical = (min.1 + 1..max.1).flat_map(|y| vec![(min.0, y), (max.0, y)]);
+    horizontal.chain(vertical).collect()
+}
+
+pub fn handle_collapse_director(
+    mut commands: Commands,
+    mut director: ResMut<CollapseDirector>,
+    mut grid: ResMut<BrickGrid>,
+    settings: Res<CollapseSettings>,
+    clock: Res<SimulationClock>,
+    level: Res<ActiveLevel>,
+    mut bricks_query: Query<(Entity, &mut Durability, Option<&CollapseWarning>)>,
+    pending_query: Query<(Entity, &BrickType, &Transform, &CollapseWarning), With<PendingBrick>>,
+) {
+    let definition = match (settings.enabled, level.definition.as_ref()) {
+        (true, Some(definition)) => definition,
+        _ => return,
+    };
+    let elapsed = clock.elapsed_seconds() * settings.pace;
+
+    // announce upcoming stages
+    while let Some(stage) = definition.collapse.get(director.next_warning) {
+        if elapsed < stage.at - stage.warning * settings.pace {
+            break
+        }
+        let index = director.next_warning;
+        match &stage.action {
+            CollapseAction::Drain { .. } => {
+                for (brick, _, _) in bricks_query.iter() {
+                    commands.entity(brick).insert(CollapseWarning(index));
+                }
+            }
+            CollapseAction::Ring { inset, kind } => {
+                let brick_type = match definition.brick_type(kind) {
+                    Some(brick_type) => brick_type,
+                    None => {
+                        warn!("collapse ring uses unknown brick type {}", kind);
+                        director.next_warning += 1;
+                        continue
+                    }
+                };
+                for cell in ring_cells(definition, *inset) {
+                    if grid.get(cell).is_some() {
+                        continue
+                    }
+                    let position = BrickGrid::cell_center(cell);
+                    commands.spawn()
+                        .insert(brick_type.clone())
+                        .insert(PendingBrick)
+                        .insert(CollapseWarning(index))
+                        .insert(Transform::from_xyz(position.x, position.y, 0.));
+                }
+            }
+        }
+        director.next_warning += 1;
+    }
+
+    // then carry them out once their time comes
+    while let Some(stage) = definition.collapse.get(director.next_stage) {
+        if elapsed < stage.at || director.next_stage >= director.next_warning {
+            break
+        }
+        let index = director.next_stage;
+        for (brick, _, warning) in bricks_query.iter() {
+            if warning.map(|warning| warning.0) == Some(index) {
+                commands.entity(brick).remove::<CollapseWarning>();
+            }
+        }
+        for (brick, brick_type, transform, warning) in pending_query.iter() {
+            if warning.0 != index {
+                continue
+            }
+            let mut brick_commands = commands.entity(brick);
+            brick_commands
+                .remove::<CollapseWarning>()
+                .remove::<PendingBrick>();
+            solidify_brick(&mut brick_commands, brick_type);
+            grid.insert(transform.translation.truncate(), brick);
+        }
+        if let CollapseAction::Drain { per_second, duration } = stage.action {
+            director.drains.push(ActiveDrain {
+                per_second,
+                remaining: duration,
+            });
+        }
+        director.next_stage += 1;
+    }
+
+    // wear down the walls while any drain is running
+    let drain: f32 = director.drains.iter().map(|drain| drain.per_second).sum();
+    if drain > 0. {
+        for (_, mut durability, _) in bricks_query.iter_mut() {
+            durability.0 -= drain * settings.drain_scale * TIME_STEP;
+        }
+    }
+    for active_drain in director.drains.iter_mut() {
+        active_drain.remaining -= TIME_STEP * settings.pace;
+    }
+    director.drains.retain(|drain| drain.remaining > 0.);
+}
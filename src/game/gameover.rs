@@ -1,7 +1,15 @@
 use bevy::{core::FixedTimestep, prelude::*};
 use bevy_prototype_lyon::prelude::ShapePlugin;
 
-use crate::{AppState, game::scoreboard::Score, utils::destroy_recursive};
+use crate::{
+    AppState,
+    game::{
+        player::{Eliminations, PlayerCount},
+        presentation::player_color,
+        scoreboard::Score,
+    },
+    utils::destroy_recursive,
+};
 
 #[derive(Component)]
 pub struct GameOverRootNode;
@@ -9,9 +17,13 @@ pub struct GameOverRootNode;
 pub fn setup(
     mut commands: Commands,
     score: Res<Score>,
+    players: Res<PlayerCount>,
+    eliminations: Res<Eliminations>,
     asset_server: Res<AssetServer>,
 ) {
     let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    // with more than one player, the last one standing is called out
+    let survivor = eliminations.0.last().filter(|_| players.0 > 1).copied();
 
     commands
         .spawn()
@@ -64,6 +76,20 @@ pub fn setup(
                         ..Default::default()
                     });
                 });
+            if let Some(survivor) = survivor {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        format!("P{} survived longest", survivor + 1),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 40.0,
+                            color: player_color(survivor),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            }
             parent
                 .spawn_bundle(ButtonBundle {
                     style: Style {
@@ -0,0 +1,447 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::cmp::Ordering;
+
+// Collision shapes, and the tests between them that physics and attacks both
+// rely on. Every test reports how two shapes overlap as a `Contact`: the
+// direction to push the first shape out of the second, and how far it has to
+// go. Shapes are described relative to their center, which is passed alongside.
+
+#[derive(Component, Clone, Debug, Deserialize)]
+pub enum Collider {
+    Ball(f32),
+    /// an axis-aligned box, described by its half extents
+    Cuboid(Vec2),
+    /// a box turned `angle` degrees anticlockwise, described by its half extents
+    Oriented { half_extents: Vec2, angle: f32 },
+    /// described by its corners, relative to the body's position
+    Triangle([Vec2; 3]),
+    /// a convex polygon, described by its corners in order around it
+    Polygon(Vec<Vec2>),
+}
+
+impl Collider {
+    /// half extents of the box bounding the collider
+    pub fn bounds(&self) -> Vec2 {
+        match self {
+            Collider::Ball(radius) => Vec2::splat(*radius),
+            Collider::Cuboid(half_extents) => *half_extents,
+            _ => self.corners(Vec2::ZERO).iter()
+                .fold(Vec2::ZERO, |bounds, point| bounds.max(point.abs())),
+        }
+    }
+
+    /// the corners of the collider centered on `center`, in order around it;
+    /// empty for a ball
+    pub fn corners(&self, center: Vec2) -> Vec<Vec2> {
+        match self {
+            Collider::Ball(_) => Vec::new(),
+            Collider::Cuboid(half_extents) => box_corners(*half_extents)
+                .iter()
+                .map(|corner| center + *corner)
+                .collect(),
+            Collider::Oriented { half_extents, angle } => {
+                let rotation = Mat2::from_angle(angle.to_radians());
+                box_corners(*half_extents)
+                    .iter()
+                    .map(|corner| center + rotation * *corner)
+                    .collect()
+            }
+            Collider::Triangle(points) => points.iter().map(|point| center + *point).collect(),
+            Collider::Polygon(points) => points.iter().map(|point| center + *point).collect(),
+        }
+    }
+
+    /// the collider flipped left to right, for characters facing left
+    pub fn mirrored(&self) -> Collider {
+        let mirror = |point: &Vec2| Vec2::new(-point.x, point.y);
+        match self {
+            Collider::Oriented { half_extents, angle } => Collider::Oriented {
+                half_extents: *half_extents,
+                angle: -angle,
+            },
+            Collider::Triangle(points) => Collider::Triangle([mirror(&points[0]), mirror(&points[1]), mirror(&points[2])]),
+            Collider::Polygon(points) => Collider::Polygon(points.iter().map(mirror).collect()),
+            collider => collider.clone(),
+        }
+    }
+}
+
+fn box_corners(half_extents: Vec2) -> [Vec2; 4] {
+    [
+        Vec2::new(-half_extents.x, -half_extents.y),
+        Vec2::new(half_extents.x, -half_extents.y),
+        Vec2::new(half_extents.x, half_extents.y),
+        Vec2::new(-half_extents.x, half_extents.y),
+    ]
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Contact {
+    /// points out of the second shape, towards the first
+    pub normal: Vec2,
+    /// how far the first shape has to move along `normal` to stop overlapping
+    pub depth: f32,
+}
+
+impl Contact {
+    /// the same contact, seen from the other shape
+    pub fn flipped(self) -> Contact {
+        Contact {
+            normal: -self.normal,
+            depth: self.depth,
+        }
+    }
+}
+
+pub fn cmp_circles(center: Vec2, radius: f32, other_center: Vec2, other_radius: f32) -> Option<Contact> {
+    let offset = center - other_center;
+    let distance = offset.length();
+    if distance >= radius + other_radius {
+        return None
+    }
+    Some(Contact {
+        // circles on top of each other are pushed apart upwards
+        normal: if distance > 0. { offset / distance } else { Vec2::Y },
+        depth: radius + other_radius - distance,
+    })
+}
+
+pub fn cmp_circle_and_aabb(
+    center: Vec2,
+    radius: f32,
+    box_center: Vec2,
+    half_extents: Vec2,
+) -> Option<Contact> {
+    let offset = center - box_center;
+    let closest = offset.clamp(-half_extents, half_extents);
+    if closest == offset {
+        // the center is inside the box, so leave through the nearest face
+        let gap = half_extents - offset.abs();
+        return Some(if gap.x < gap.y {
+            Contact {
+                normal: Vec2::new(offset.x.signum(), 0.),
+                depth: gap.x + radius,
+            }
+        } else {
+            Contact {
+                normal: Vec2::new(0., offset.y.signum()),
+                depth: gap.y + radius,
+            }
+        })
+    }
+    let to_center = offset - closest;
+    let distance = to_center.length();
+    if distance < radius {
+        Some(Contact {
+            normal: to_center / distance,
+            depth: radius - distance,
+        })
+    } else {
+        None
+    }
+}
+
+fn closest_point_on_segment(point: Vec2, start: Vec2, end: Vec2) -> Vec2 {
+    let edge = end - start;
+    if edge.length_squared() == 0. {
+        return start
+    }
+    let along = (point - start).dot(edge) / edge.length_squared();
+    start + edge * along.clamp(0., 1.)
+}
+
+// each edge of a polygon, from each corner to the next
+fn edges(corners: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    corners.iter()
+        .zip(corners.iter().cycle().skip(1))
+        .map(|(start, end)| (*start, *end))
+}
+
+// 1 if the corners go anticlockwise, -1 if they go clockwise
+fn winding(corners: &[Vec2]) -> f32 {
+    edges(corners)
+        .map(|(start, end)| start.perp_dot(end))
+        .sum::<f32>()
+        .signum()
+}
+
+/// A circle against a convex polygon, given by its corners in the world, in
+/// either winding. Triangles and oriented boxes go through here too.
+pub fn cmp_circle_and_polygon(center: Vec2, radius: f32, corners: &[Vec2]) -> Option<Contact> {
+    if corners.len() < 3 {
+        return None
+    }
+    // which side of each edge is inside depends on the winding of the corners
+    let winding = winding(corners);
+    let is_inside = edges(corners)
+        .all(|(start, end)| (end - start).perp_dot(center - start) * winding >= 0.);
+
+    let nearest_edge = edges(corners)
+        .min_by(|(a1, a2), (b1, b2)| {
+            let distance_a = (closest_point_on_segment(center, *a1, *a2) - center).length_squared();
+            let distance_b = (closest_point_on_segment(center, *b1, *b2) - center).length_squared();
+            distance_a.partial_cmp(&distance_b).unwrap_or(Ordering::Equal)
+        })?;
+    let closest = closest_point_on_segment(center, nearest_edge.0, nearest_edge.1);
+
+    if is_inside {
+        // leave through the nearest edge
+        let (start, end) = nearest_edge;
+        let outward = (end - start).perp().normalize() * -winding;
+        return Some(Contact {
+            normal: outward,
+            depth: (closest - center).length() + radius,
+        })
+    }
+
+    let to_center = center - closest;
+    let distance = to_center.length();
+    if distance < radius {
+        Some(Contact {
+            normal: to_center / distance,
+            depth: radius - distance,
+        })
+    } else {
+        None
+    }
+}
+
+// the range a polygon covers along `axis`
+fn project(corners: &[Vec2], axis: Vec2) -> (f32, f32) {
+    corners.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), corner| {
+        let along = corner.dot(axis);
+        (min.min(along), max.max(along))
+    })
+}
+
+fn centroid(corners: &[Vec2]) -> Vec2 {
+    corners.iter().fold(Vec2::ZERO, |sum, corner| sum + *corner) / corners.len() as f32
+}
+
+/// Two convex polygons, given by their corners in the world. They overlap
+/// unless one of their edges separates them, and the edge they overlap least
+/// across is the way out.
+pub fn cmp_polygons(corners: &[Vec2], other_corners: &[Vec2]) -> Option<Contact> {
+    if corners.len() < 3 || other_corners.len() < 3 {
+        return None
+    }
+    let offset = centroid(corners) - centroid(other_corners);
+    let mut best: Option<Contact> = None;
+    for (start, end) in edges(corners).chain(edges(other_corners)) {
+        let axis = (end - start).perp().normalize_or_zero();
+        if axis == Vec2::ZERO {
+            continue
+        }
+        let (min, max) = project(corners, axis);
+        let (other_min, other_max) = project(other_corners, axis);
+        let depth = (max - other_min).min(other_max - min);
+        if depth <= 0. {
+            return None
+        }
+        if best.map_or(true, |best| depth < best.depth) {
+            best = Some(Contact {
+                normal: if offset.dot(axis) < 0. { -axis } else { axis },
+                depth,
+            });
+        }
+    }
+    best
+}
+
+/// How `body` overlaps `other`, if it does. The normal points out of `other`.
+pub fn cmp_colliders(body: (&Collider, Vec2), other: (&Collider, Vec2)) -> Option<Contact> {
+    match (body, other) {
+        ((Collider::Ball(radius), center), (Collider::Ball(other_radius), other_center)) => {
+            cmp_circles(center, *radius, other_center, *other_radius)
+        }
+        ((Collider::Ball(radius), center), (Collider::Cuboid(half_extents), box_center)) => {
+            cmp_circle_and_aabb(center, *radius, box_center, *half_extents)
+        }
+        ((Collider::Ball(radius), center), (other_collider, other_center)) => {
+            cmp_circle_and_polygon(center, *radius, &other_collider.corners(other_center))
+        }
+        (_, (Collider::Ball(_), _)) => cmp_colliders(other, body).map(Contact::flipped),
+        ((collider, center), (other_collider, other_center)) => {
+            cmp_polygons(&collider.corners(center), &other_collider.corners(other_center))
+        }
+    }
+}
+
+/// Whether two colliders overlap. Used for queries like attack hits, which
+/// don't need the contact resolved.
+pub fn overlaps(a: (&Collider, Vec2), b: (&Collider, Vec2)) -> bool {
+    cmp_colliders(a, b).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // the shape jabs hit with, for a character facing right
+    fn jab() -> Collider {
+        Collider::Triangle([Vec2::new(0., -12.5), Vec2::new(20., 0.), Vec2::new(0., 12.5)])
+    }
+
+    fn assert_contact(contact: Option<Contact>, normal: Vec2, depth: f32) {
+        let contact = contact.expect("the shapes should overlap");
+        assert!((contact.normal - normal).length() < 1e-4, "normal {:?}, expected {:?}", contact.normal, normal);
+        assert!((contact.depth - depth).abs() < 1e-4, "depth {}, expected {}", contact.depth, depth);
+    }
+
+    #[test]
+    fn balls() {
+        let ball = Collider::Ball(10.);
+        assert_contact(cmp_colliders((&ball, Vec2::new(15., 0.)), (&ball, Vec2::ZERO)), Vec2::X, 5.);
+        assert_contact(cmp_colliders((&ball, Vec2::new(0., -12.)), (&ball, Vec2::ZERO)), -Vec2::Y, 8.);
+        // only just touching isn't overlapping
+        assert!(cmp_colliders((&ball, Vec2::new(20., 0.)), (&ball, Vec2::ZERO)).is_none());
+        assert!(cmp_colliders((&ball, Vec2::new(30., 30.)), (&ball, Vec2::ZERO)).is_none());
+    }
+
+    #[test]
+    fn ball_and_cuboid() {
+        let ball = Collider::Ball(5.);
+        let cuboid = Collider::Cuboid(Vec2::splat(10.));
+        assert_contact(cmp_colliders((&ball, Vec2::new(0., 12.)), (&cuboid, Vec2::ZERO)), Vec2::Y, 3.);
+        assert_contact(cmp_colliders((&ball, Vec2::new(-13., 0.)), (&cuboid, Vec2::ZERO)), -Vec2::X, 2.);
+        // off a corner, along the diagonal
+        let corner = Vec2::splat(10.) + Vec2::splat(3. / 2f32.sqrt());
+        assert_contact(cmp_colliders((&ball, corner), (&cuboid, Vec2::ZERO)), Vec2::ONE.normalize(), 2.);
+        // with its center inside, out through the nearest face
+        assert_contact(cmp_colliders((&ball, Vec2::new(8., 0.)), (&cuboid, Vec2::ZERO)), Vec2::X, 7.);
+        assert!(cmp_colliders((&ball, Vec2::new(0., 16.)), (&cuboid, Vec2::ZERO)).is_none());
+        assert!(cmp_colliders((&ball, Vec2::new(14., 14.)), (&cuboid, Vec2::ZERO)).is_none());
+        // the other way round, the normal points out of the ball
+        assert_contact(cmp_colliders((&cuboid, Vec2::ZERO), (&ball, Vec2::new(0., 12.))), -Vec2::Y, 3.);
+    }
+
+    #[test]
+    fn ball_and_oriented_box() {
+        let ball = Collider::Ball(5.);
+        // a diamond, with a corner pointing straight up
+        let diamond = Collider::Oriented { half_extents: Vec2::splat(10.), angle: 45. };
+        let top = 200f32.sqrt();
+        assert_contact(cmp_colliders((&ball, Vec2::new(0., top + 3.)), (&diamond, Vec2::ZERO)), Vec2::Y, 2.);
+        assert!(cmp_colliders((&ball, Vec2::new(0., top + 6.)), (&diamond, Vec2::ZERO)).is_none());
+        // against a face, which faces up and to the right
+        let face = Vec2::ONE.normalize();
+        assert_contact(cmp_colliders((&ball, face * 14.), (&diamond, Vec2::ZERO)), face, 1.);
+        assert!(cmp_colliders((&ball, face * 16.), (&diamond, Vec2::ZERO)).is_none());
+    }
+
+    #[test]
+    fn ball_and_polygon_in_either_winding() {
+        let ball = Collider::Ball(5.);
+        let corners = vec![Vec2::new(-10., -10.), Vec2::new(10., -10.), Vec2::new(10., 10.), Vec2::new(-10., 10.)];
+        let anticlockwise = Collider::Polygon(corners.clone());
+        let clockwise = Collider::Polygon(corners.into_iter().rev().collect());
+        for polygon in [anticlockwise, clockwise] {
+            assert_contact(cmp_colliders((&ball, Vec2::new(13., 0.)), (&polygon, Vec2::ZERO)), Vec2::X, 2.);
+            assert_contact(cmp_colliders((&ball, Vec2::new(0., -6.)), (&polygon, Vec2::ZERO)), -Vec2::Y, 9.);
+            assert!(cmp_colliders((&ball, Vec2::new(16., 0.)), (&polygon, Vec2::ZERO)).is_none());
+        }
+    }
+
+    #[test]
+    fn jab_hits_with_its_tip() {
+        let target = Collider::Ball(25.);
+        // well past where the old center-distance check reached, but inside the tip
+        assert_contact(cmp_colliders((&target, Vec2::new(40., 0.)), (&jab(), Vec2::ZERO)), Vec2::X, 5.);
+        assert!(overlaps((&jab(), Vec2::ZERO), (&target, Vec2::new(40., 0.))));
+        // just past the tip, and above the flat back
+        assert!(!overlaps((&jab(), Vec2::ZERO), (&target, Vec2::new(46., 0.))));
+        assert!(!overlaps((&jab(), Vec2::ZERO), (&target, Vec2::new(0., 38.))));
+        // facing left, it points the other way
+        assert_contact(cmp_colliders((&target, Vec2::new(-40., 0.)), (&jab().mirrored(), Vec2::ZERO)), -Vec2::X, 5.);
+        assert!(!overlaps((&jab().mirrored(), Vec2::ZERO), (&target, Vec2::new(40., 0.))));
+    }
+
+    #[test]
+    fn polygons() {
+        let cuboid = Collider::Cuboid(Vec2::splat(10.));
+        assert_contact(cmp_colliders((&cuboid, Vec2::new(15., 2.)), (&cuboid, Vec2::ZERO)), Vec2::X, 5.);
+        assert!(cmp_colliders((&cuboid, Vec2::new(20., 0.)), (&cuboid, Vec2::ZERO)).is_none());
+        // the jab's tip pokes 5 into a box in front of it
+        assert_contact(cmp_colliders((&jab(), Vec2::ZERO), (&cuboid, Vec2::new(25., 0.))), -Vec2::X, 5.);
+        assert!(cmp_colliders((&jab(), Vec2::ZERO), (&cuboid, Vec2::new(31., 0.))).is_none());
+        // a diamond's bottom corner resting 2 into a box below it
+        let diamond = Collider::Oriented { half_extents: Vec2::splat(10.), angle: 45. };
+        let above = Vec2::new(0., 10. + 200f32.sqrt() - 2.);
+        assert_contact(cmp_colliders((&diamond, above), (&cuboid, Vec2::ZERO)), Vec2::Y, 2.);
+        assert!(cmp_colliders((&diamond, above + Vec2::new(0., 3.)), (&cuboid, Vec2::ZERO)).is_none());
+    }
+
+    fn point() -> impl Strategy<Value = Vec2> {
+        (-40f32..40., -40f32..40.).prop_map(|(x, y)| Vec2::new(x, y))
+    }
+
+    fn center() -> impl Strategy<Value = Vec2> {
+        (-60f32..60., -60f32..60.).prop_map(|(x, y)| Vec2::new(x, y))
+    }
+
+    fn collider() -> impl Strategy<Value = Collider> {
+        prop_oneof![
+            (1f32..40.).prop_map(Collider::Ball),
+            (1f32..40., 1f32..40.).prop_map(|(x, y)| Collider::Cuboid(Vec2::new(x, y))),
+            (1f32..40., 1f32..40., -180f32..180.).prop_map(|(x, y, angle)| Collider::Oriented {
+                half_extents: Vec2::new(x, y),
+                angle,
+            }),
+            (point(), point(), point())
+                .prop_filter("too thin to be a triangle", |(a, b, c)| (*b - *a).perp_dot(*c - *a).abs() > 20.)
+                .prop_map(|(a, b, c)| Collider::Triangle([a, b, c])),
+            // regular polygons, which are always convex
+            (3usize..8, 5f32..40., 0f32..360.).prop_map(|(sides, radius, turn)| Collider::Polygon(
+                (0..sides)
+                    .map(|side| {
+                        let angle = (turn + side as f32 * 360. / sides as f32).to_radians();
+                        Vec2::new(angle.cos(), angle.sin()) * radius
+                    })
+                    .collect()
+            )),
+        ]
+    }
+
+    // a contact no deeper than this may be found one way and missed the other
+    // to rounding
+    const TOLERANCE: f32 = 1e-2;
+
+    fn same_contact(a: Option<Contact>, b: Option<Contact>) -> bool {
+        match (a, b) {
+            (None, None) => true,
+            (Some(a), Some(b)) => (a.normal - b.normal).length() < TOLERANCE && (a.depth - b.depth).abs() < TOLERANCE,
+            (Some(contact), None) | (None, Some(contact)) => contact.depth < TOLERANCE,
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn contacts_are_symmetric(a in collider(), a_center in center(), b in collider(), b_center in center()) {
+            // shapes on top of each other are pushed apart the same way either way round
+            prop_assume!(a_center.distance(b_center) > 1.);
+            let forward = cmp_colliders((&a, a_center), (&b, b_center));
+            let backward = cmp_colliders((&b, b_center), (&a, a_center));
+            prop_assert!(same_contact(forward, backward.map(Contact::flipped)), "{:?} against {:?}", forward, backward);
+            if let Some(contact) = forward {
+                prop_assert!(contact.depth > 0.);
+                prop_assert!((contact.normal.length() - 1.).abs() < 1e-3);
+            }
+        }
+
+        #[test]
+        fn contacts_ignore_where_they_happen(
+            a in collider(),
+            a_center in center(),
+            b in collider(),
+            b_center in center(),
+            offset in (-500f32..500., -500f32..500.).prop_map(|(x, y)| Vec2::new(x, y)),
+        ) {
+            prop_assume!(a_center.distance(b_center) > 1.);
+            let here = cmp_colliders((&a, a_center), (&b, b_center));
+            let there = cmp_colliders((&a, a_center + offset), (&b, b_center + offset));
+            prop_assert!(same_contact(here, there), "{:?} moved to {:?}", here, there);
+        }
+    }
+}
@@ -1,50 +1,110 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+use crate::{AppState, game::bindings::{Bindings, Control}};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ActionType {
     Jump,
     Jab,
     Stomp,
     Counter,
+    Shield,
+    Dodge,
+    Grab,
 }
 
-#[derive(Debug)]
+impl ActionType {
+    /// When several buffered actions could go at once, the highest goes first:
+    /// getting out of the way before jumping, and jumping before attacking.
+    pub fn priority(&self) -> u8 {
+        match self {
+            ActionType::Dodge => 6,
+            ActionType::Shield => 5,
+            ActionType::Jump => 4,
+            ActionType::Grab => 3,
+            ActionType::Counter => 2,
+            ActionType::Stomp => 1,
+            ActionType::Jab => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Trigger {
     PlayerAction(ActionType),
     PlayerMovement(f32, f32),
-    Pause,
 }
 
 fn bool_to_num(foo: bool) -> f32 {
     if foo { 1. } else { 0. }
 }
 
+/// An input from the local player in the given slot.
 #[derive(Debug)]
-pub struct Event(pub Trigger);
+pub struct Event(pub usize, pub Trigger);
+
+/// Something a local player plays with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Device {
+    Keyboard,
+    Gamepad(Gamepad),
+}
+
+/// The device each local player claimed on the join screen, by slot.
+#[derive(Default)]
+pub struct Roster(pub Vec<Device>);
+
+impl Roster {
+    /// the slot of the player using `device`, if anyone is
+    pub fn slot(&self, device: Device) -> Option<usize> {
+        self.0.iter().position(|claimed| *claimed == device)
+    }
+}
+
+/// The movement last sent for each device. Movement is only sent when it
+/// changes, so holding a direction doesn't fill the replay with copies of it.
+#[derive(Default)]
+pub struct SentMovement(HashMap<Device, Vec2>);
+
+impl SentMovement {
+    /// records `movement` for `device`, returning whether it's new
+    fn update(&mut self, device: Device, movement: Vec2) -> bool {
+        self.0.insert(device, movement) != Some(movement)
+    }
+}
+
+/// Characters start out standing still, so whatever is held is sent again
+/// once a match starts.
+pub fn reset_sent_movement(mut sent: ResMut<SentMovement>) {
+    sent.0.clear();
+}
 
 pub fn handle_keyboard_input(
 	mut writer: EventWriter<Event>,
     keys: Res<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    roster: Res<Roster>,
+    mut sent: ResMut<SentMovement>,
 ) {
-    if keys.just_pressed(KeyCode::Escape) {
-        writer.send(Event(Trigger::Pause));
-    }
-    if keys.just_pressed(KeyCode::Space) {
-        writer.send(Event(Trigger::PlayerAction(ActionType::Jump)));
-    }
-    if keys.just_pressed(KeyCode::R) {
-        writer.send(Event(Trigger::PlayerAction(ActionType::Counter)));
-    }
-    if keys.just_pressed(KeyCode::Q) {
-        writer.send(Event(Trigger::PlayerAction(ActionType::Jab)));
-    }
-    if keys.just_pressed(KeyCode::E) {
-        writer.send(Event(Trigger::PlayerAction(ActionType::Stomp)));
+    let player = match roster.slot(Device::Keyboard) {
+        Some(player) => player,
+        None => return,
+    };
+    for control in Control::ALL {
+        if let Some(action) = control.action() {
+            if bindings.is_key_just_pressed(control, &keys) {
+                writer.send(Event(player, Trigger::PlayerAction(action)));
+            }
+        }
     }
-    let x = bool_to_num(keys.pressed(KeyCode::D)) - bool_to_num(keys.just_pressed(KeyCode::A));
-    let y = bool_to_num(keys.just_pressed(KeyCode::W)) - bool_to_num(keys.just_pressed(KeyCode::S));
-    if x != 0. && y != 0. {
-        writer.send(Event(Trigger::PlayerMovement(x, y)))
+    let held = |control: Control| bool_to_num(bindings.is_key_pressed(control, &keys));
+    let movement = Vec2::new(
+        held(Control::Right) - held(Control::Left),
+        held(Control::Up) - held(Control::Down),
+    );
+    if sent.update(Device::Keyboard, movement) {
+        writer.send(Event(player, Trigger::PlayerMovement(movement.x, movement.y)))
     }
 }
 
@@ -52,45 +112,82 @@ pub fn handle_gamepad_input(
 	mut writer: EventWriter<Event>,
 	gamepads: Res<Gamepads>,
 	button_inputs: Res<Input<GamepadButton>>,
-	button_axes: Res<Axis<GamepadButton>>,
 	axes: Res<Axis<GamepadAxis>>,
-    keys: Res<Input<KeyCode>>,
+    bindings: Res<Bindings>,
+    roster: Res<Roster>,
+    mut sent: ResMut<SentMovement>,
 ) {
     for gamepad in gamepads.iter().cloned() {
-        if button_inputs.just_pressed(GamepadButton(gamepad, GamepadButtonType::Start)) {
-            writer.send(Event(Trigger::Pause));
-        }
-
-        if button_inputs.just_pressed(GamepadButton(gamepad, GamepadButtonType::South)) {
-            writer.send(Event(Trigger::PlayerAction(ActionType::Jump)));
-        }
-        if button_inputs.just_pressed(GamepadButton(gamepad, GamepadButtonType::North)) {
-            writer.send(Event(Trigger::PlayerAction(ActionType::Counter)));
-
-        }
-        if button_inputs.just_pressed(GamepadButton(gamepad, GamepadButtonType::West)) {
-            writer.send(Event(Trigger::PlayerAction(ActionType::Jab)));
-        }
-        if button_inputs.just_pressed(GamepadButton(gamepad, GamepadButtonType::East)) {
-            writer.send(Event(Trigger::PlayerAction(ActionType::Stomp)));
+        // gamepads nobody joined with are ignored
+        let player = match roster.slot(Device::Gamepad(gamepad)) {
+            Some(player) => player,
+            None => continue,
+        };
+        for control in Control::ALL {
+            if let Some(action) = control.action() {
+                if bindings.is_button_just_pressed(control, gamepad, &button_inputs) {
+                    writer.send(Event(player, Trigger::PlayerAction(action)));
+                }
+            }
         }
 
-        let movement_input_x = axes.get(
-            GamepadAxis(gamepad, GamepadAxisType::LeftStickX)
+        // bound buttons, like the d-pad, take over from the stick while they're held
+        let held = |control: Control| bool_to_num(bindings.is_button_pressed(control, gamepad, &button_inputs));
+        let buttons = Vec2::new(
+            held(Control::Right) - held(Control::Left),
+            held(Control::Up) - held(Control::Down),
         );
-        let movement_input_y = axes.get(
-            GamepadAxis(gamepad, GamepadAxisType::LeftStickY)
-        );
-        let movement_input_vec = if movement_input_x.is_none() && movement_input_y.is_none() {
-            None
+        let movement = if buttons != Vec2::ZERO {
+            buttons
         } else {
-            Some((
-                movement_input_x.unwrap_or(0.),
-                movement_input_y.unwrap_or(0.),
-            ))
+            let stick = Vec2::new(
+                axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.),
+                axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickY)).unwrap_or(0.),
+            );
+            bindings.stick.apply(stick)
         };
-        if let Some((x, y)) = movement_input_vec {
-            writer.send(Event(Trigger::PlayerMovement(x, y)))
+        if sent.update(Device::Gamepad(gamepad), movement) {
+            writer.send(Event(player, Trigger::PlayerMovement(movement.x, movement.y)))
         }
     }
 }
+
+/// Pauses the match when anyone presses pause, on any device.
+pub fn handle_pause_input(
+    mut state: ResMut<State<AppState>>,
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+    bindings: Res<Bindings>,
+) {
+    if is_pause_pressed(&bindings, &keys, &gamepads, &button_inputs) {
+        // a transition already queued this frame, like the match ending, wins
+        let _ = state.push(AppState::Paused);
+    }
+}
+
+pub fn is_pause_pressed(
+    bindings: &Bindings,
+    keys: &Input<KeyCode>,
+    gamepads: &Gamepads,
+    button_inputs: &Input<GamepadButton>,
+) -> bool {
+    bindings.is_key_just_pressed(Control::Pause, keys)
+        || gamepads.iter().any(|gamepad| bindings.is_button_just_pressed(Control::Pause, *gamepad, button_inputs))
+}
+
+/// Forgets the presses made this frame, so the press that opens or closes a
+/// menu isn't read a second time by whatever runs after it.
+pub fn clear_just_pressed(
+    mut keys: ResMut<Input<KeyCode>>,
+    mut button_inputs: ResMut<Input<GamepadButton>>,
+) {
+    let pressed_keys: Vec<KeyCode> = keys.get_just_pressed().copied().collect();
+    for key in pressed_keys {
+        keys.reset(key);
+    }
+    let pressed_buttons: Vec<GamepadButton> = button_inputs.get_just_pressed().copied().collect();
+    for button in pressed_buttons {
+        button_inputs.reset(button);
+    }
+}
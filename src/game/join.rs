@@ -0,0 +1,205 @@
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    game::{
+        bindings::{Bindings, Control},
+        input::{Device, Roster},
+        player::{ai::AiDifficulty, PlayerCount, MAX_PLAYERS},
+        presentation::player_color,
+    },
+};
+
+// Before a match, every local player claims a slot by pressing join on the
+// device they want to play with, and can give it up again with leave. Once
+// someone has joined, any player can start the match. Anyone can change how
+// well the enemies play before it starts. All four are bindings like any
+// other control, so they can be changed from the pause menu.
+
+#[derive(Component)]
+pub struct JoinRootNode;
+/// The text describing one of the slots on the join screen.
+#[derive(Component)]
+pub struct SlotNode(pub usize);
+/// The text showing the enemy difficulty picked for the match.
+#[derive(Component)]
+pub struct DifficultyNode;
+
+const BACKGROUND: Color = Color::rgb(0.1, 0.2, 0.45);
+const OPEN_SLOT: Color = Color::rgb(0.5, 0.5, 0.5);
+
+fn slot_label(slot: usize, roster: &Roster, bindings: &Bindings) -> String {
+    match roster.0.get(slot) {
+        Some(Device::Keyboard) => format!("P{}: keyboard", slot + 1),
+        Some(Device::Gamepad(gamepad)) => format!("P{}: gamepad {}", slot + 1, gamepad.0 + 1),
+        None => format!("P{}: press {} to join", slot + 1, bindings.prompt(Control::Join)),
+    }
+}
+
+fn difficulty_label(difficulty: &AiDifficulty, bindings: &Bindings) -> String {
+    format!("enemies: {:?} - {} to change", difficulty.0, bindings.prompt(Control::ChangeDifficulty))
+}
+
+fn slot_color(slot: usize, roster: &Roster) -> Color {
+    if slot < roster.0.len() { player_color(slot) } else { OPEN_SLOT }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    roster: Res<Roster>,
+    difficulty: Res<AiDifficulty>,
+    bindings: Res<Bindings>,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+
+    commands
+        .spawn()
+        .insert(JoinRootNode)
+        .insert_bundle(NodeBundle {
+            color: BACKGROUND.into(),
+            style: Style {
+                flex_grow: 1.,
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for slot in 0..MAX_PLAYERS {
+                parent
+                    .spawn()
+                    .insert(SlotNode(slot))
+                    .insert_bundle(TextBundle {
+                        text: Text::with_section(
+                            slot_label(slot, &roster, &bindings),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 40.0,
+                                color: slot_color(slot, &roster),
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+            }
+            parent
+                .spawn()
+                .insert(DifficultyNode)
+                .insert_bundle(TextBundle {
+                    text: Text::with_section(
+                        difficulty_label(&difficulty, &bindings),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 30.0,
+                            color: Color::rgb(0.9, 0.9, 0.9),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    format!(
+                        "{} to leave, {} to fight",
+                        bindings.prompt(Control::Leave),
+                        bindings.prompt(Control::Start),
+                    ),
+                    TextStyle {
+                        font,
+                        font_size: 30.0,
+                        color: Color::rgb(0.9, 0.9, 0.9),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+#[derive(Clone, Copy)]
+enum JoinRequest {
+    Join,
+    Leave,
+    Start,
+    ChangeDifficulty,
+}
+
+// the controls read on the join screen, and what each asks for
+const JOIN_CONTROLS: [(Control, JoinRequest); 4] = [
+    (Control::Join, JoinRequest::Join),
+    (Control::Leave, JoinRequest::Leave),
+    (Control::Start, JoinRequest::Start),
+    (Control::ChangeDifficulty, JoinRequest::ChangeDifficulty),
+];
+
+pub fn handle_join_input(
+    mut state: ResMut<State<AppState>>,
+    mut roster: ResMut<Roster>,
+    mut players: ResMut<PlayerCount>,
+    mut difficulty: ResMut<AiDifficulty>,
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+    bindings: Res<Bindings>,
+) {
+    let mut requests = Vec::new();
+    for (control, request) in JOIN_CONTROLS {
+        if bindings.is_key_just_pressed(control, &keys) {
+            requests.push((Device::Keyboard, request));
+        }
+        for gamepad in gamepads.iter().cloned() {
+            if bindings.is_button_just_pressed(control, gamepad, &button_inputs) {
+                requests.push((Device::Gamepad(gamepad), request));
+            }
+        }
+    }
+
+    for (device, request) in requests {
+        match request {
+            JoinRequest::Join => {
+                if roster.slot(device).is_none() && roster.0.len() < MAX_PLAYERS {
+                    roster.0.push(device);
+                }
+            }
+            JoinRequest::Leave => {
+                if let Some(slot) = roster.slot(device) {
+                    roster.0.remove(slot);
+                }
+            }
+            // only someone who has joined can start the match
+            JoinRequest::Start => {
+                if roster.slot(device).is_some() {
+                    players.0 = roster.0.len();
+                    state.set(AppState::Game).unwrap();
+                    return
+                }
+            }
+            JoinRequest::ChangeDifficulty => {
+                difficulty.0 = difficulty.0.next();
+            }
+        }
+    }
+}
+
+pub fn handle_ui(
+    roster: Res<Roster>,
+    difficulty: Res<AiDifficulty>,
+    bindings: Res<Bindings>,
+    mut slot_query: Query<(&mut Text, &SlotNode), Without<DifficultyNode>>,
+    mut difficulty_query: Query<&mut Text, With<DifficultyNode>>,
+) {
+    if roster.is_changed() {
+        for (mut text, slot) in slot_query.iter_mut() {
+            text.sections[0].value = slot_label(slot.0, &roster, &bindings);
+            text.sections[0].style.color = slot_color(slot.0, &roster);
+        }
+    }
+    if difficulty.is_changed() {
+        for mut text in difficulty_query.iter_mut() {
+            text.sections[0].value = difficulty_label(&difficulty, &bindings);
+        }
+    }
+}
@@ -1,18 +1,210 @@
-use bevy::prelude::*;
-use bevy_prototype_lyon::prelude::*;
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    ecs::system::EntityCommands,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use serde::Deserialize;
+use std::collections::BTreeSet;
 
-use crate::audio;
+use crate::{
+    audio,
+    game::{
+        collapse::CollapseStage,
+        physics::{Collider, DynamicBody, FixedBody, OneWay, Restitution, Velocity},
+        player,
+        rng::MatchRng,
+    },
+};
 
 pub const BRICK_APOTHEM: f32 = 15.;
 
-const BRICKS_WIDE: i32 = 40;
-const BRICKS_WIDE_F: f32 = 40.;
-const BRICKS_TALL: i32 = 22;
-const BRICKS_TALL_F: f32 = 22.;
+pub const DEFAULT_LEVEL: &str = "levels/arena.level.ron";
 
-pub const LEVEL_WIDTH: f32 = BRICKS_WIDE_F * BRICK_APOTHEM;
-pub const LEVEL_HEIGHT: f32 = BRICKS_TALL_F * BRICK_APOTHEM;
+/// An axis-aligned area of the level, in world coordinates.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Bounds {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Bounds {
+    pub fn contains(&self, point: Vec2) -> bool {
+        self.min.x <= point.x && point.x <= self.max.x && self.min.y <= point.y && point.y <= self.max.y
+    }
+}
+
+/// What a brick is made of, which decides how it behaves when hit and broken.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum BrickMaterial {
+    Standard,
+    /// sends characters back off it, keeping `restitution` of their speed
+    Bouncy { restitution: f32 },
+    /// can't be worn down at all
+    Unbreakable,
+    /// when broken, wears down every brick within `radius` by `damage` and
+    /// throws nearby characters clear at up to `launch` speed
+    Explosive { radius: f32, damage: f32, launch: f32 },
+    /// grows back `delay` seconds after it breaks
+    Regenerating { delay: f32 },
+}
+
+impl Default for BrickMaterial {
+    fn default() -> Self {
+        BrickMaterial::Standard
+    }
+}
 
+/// A kind of brick a level can place. Every brick keeps a copy of its type.
+#[derive(Component, Clone, Debug, Deserialize)]
+pub struct BrickType {
+    pub name: String,
+    pub durability: f32,
+    #[serde(default)]
+    pub material: BrickMaterial,
+}
+
+/// A single brick, placed on a cell of the arena grid.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BrickPlacement {
+    pub kind: String,
+    pub cell: Cell,
+}
+
+/// A straight run of bricks between two cells, both ends included.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BrickLine {
+    pub kind: String,
+    pub from: Cell,
+    pub to: Cell,
+}
+
+impl BrickLine {
+    fn cells(&self) -> Vec<Cell> {
+        let (dx, dy) = (self.to.0 - self.from.0, self.to.1 - self.from.1);
+        let steps = dx.abs().max(dy.abs());
+        (0..=steps)
+            .map(|step| {
+                if steps == 0 {
+                    self.from
+                } else {
+                    (self.from.0 + dx * step / steps, self.from.1 + dy * step / steps)
+                }
+            })
+            .collect()
+    }
+}
+
+/// Interior geometry that isn't made of bricks. Platforms can't be broken.
+#[derive(Clone, Debug, Deserialize)]
+pub enum PlatformDefinition {
+    /// a solid floating block
+    Block { center: Vec2, half_extents: Vec2 },
+    /// a thin floor that can be jumped through from below and landed on from above
+    OneWay { center: Vec2, half_width: f32 },
+    /// a solid triangle, given by its corners
+    Slope { points: [Vec2; 3] },
+}
+
+const ONE_WAY_THICKNESS: f32 = 8.;
+
+/// The layout of a level, loaded from a `.level.ron` file.
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "82279c5a-43cc-4008-8f80-5db98639e59b"]
+pub struct LevelDefinition {
+    /// the open space characters are spawned into when no spawn point is given
+    pub arena: Bounds,
+    /// characters outside of this area are out of the match
+    pub kill_zone: Bounds,
+    pub brick_types: Vec<BrickType>,
+    #[serde(default)]
+    pub lines: Vec<BrickLine>,
+    #[serde(default)]
+    pub bricks: Vec<BrickPlacement>,
+    #[serde(default)]
+    pub platforms: Vec<PlatformDefinition>,
+    pub player_spawn: Vec2,
+    #[serde(default)]
+    pub enemy_spawns: Vec<Vec2>,
+    /// how the arena closes in over the course of a match, in order
+    #[serde(default)]
+    pub collapse: Vec<CollapseStage>,
+}
+
+impl LevelDefinition {
+    pub fn brick_type(&self, name: &str) -> Option<&BrickType> {
+        self.brick_types.iter().find(|brick_type| brick_type.name == name)
+    }
+
+    /// every brick in the level with its type, lines first
+    pub fn brick_cells(&self) -> Vec<(Cell, &BrickType)> {
+        let lines = self.lines.iter()
+            .flat_map(|line| line.cells().into_iter().map(move |cell| (cell, line.kind.as_str())));
+        let bricks = self.bricks.iter()
+            .map(|brick| (brick.cell, brick.kind.as_str()));
+        lines.chain(bricks)
+            .filter_map(|(cell, kind)| match self.brick_type(kind) {
+                Some(brick_type) => Some((cell, brick_type)),
+                None => {
+                    warn!("level places a brick of unknown type {}", kind);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let level: LevelDefinition = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/// Which level file the next match is played on.
+pub struct LevelSelection(pub String);
+
+impl Default for LevelSelection {
+    fn default() -> Self {
+        LevelSelection(DEFAULT_LEVEL.to_string())
+    }
+}
+
+/// The level of the match in progress. The simulation doesn't start until the
+/// level has loaded and been spawned.
+#[derive(Default)]
+pub struct ActiveLevel {
+    pub handle: Handle<LevelDefinition>,
+    pub definition: Option<LevelDefinition>,
+}
+
+impl ActiveLevel {
+    pub fn is_spawned(&self) -> bool {
+        self.definition.is_some()
+    }
+
+    pub fn kill_zone(&self) -> Option<Bounds> {
+        self.definition.as_ref().map(|definition| definition.kill_zone)
+    }
+}
+
+/// How much more wear a brick can take before it breaks. Bricks without one,
+/// like unbreakable ones, can't be damaged.
 #[derive(Component)]
 pub struct Durability(pub f32);
 
@@ -22,88 +214,341 @@ impl Default for Durability {
 	}
 }
 
-pub fn setup(mut commands: Commands) {
-    let brick_shape = shapes::RegularPolygon {
-		sides: 4,
-		feature: shapes::RegularPolygonFeature::Apothem(BRICK_APOTHEM),
-		..Default::default()
-	};
-
-	let mut spawn_brick = |x: f32, y: f32| {
-		commands.spawn()
-			.insert(Durability::default())
-			.insert_bundle(GeometryBuilder::build_as(
-				&brick_shape,
-				DrawMode::Outlined {
-					fill_mode: FillMode::color(Color::BLACK),
-					outline_mode: StrokeMode::new(Color::MAROON, 4.0),
-				},
-				Transform::from_xyz(x, y, 0.),
-			));
-	};
-
-	// spawn top and bottom wall
-	for count in 0..=BRICKS_WIDE {
-		let position: f32 = (count - BRICKS_WIDE / 2) as f32;
-		let x: f32 = BRICK_APOTHEM * 2. * position;
-		spawn_brick(x, BRICK_APOTHEM * BRICKS_TALL_F);
-		spawn_brick(x, -BRICK_APOTHEM * BRICKS_TALL_F);
-	}
-	// spawn left and right wall
-	for count in 1..BRICKS_TALL {
-		let position: f32 = (count - BRICKS_TALL / 2) as f32;
-		let y: f32 = BRICK_APOTHEM * 2. * position;
-		spawn_brick(-BRICK_APOTHEM * BRICKS_WIDE_F, y);
-		spawn_brick(BRICK_APOTHEM * BRICKS_WIDE_F, y);
-	}
+pub const CELL_SIZE: f32 = BRICK_APOTHEM * 2.;
+
+pub type Cell = (i32, i32);
+
+/// Every brick, indexed by the arena cell it occupies, so that collision and
+/// AI queries only ever look at the cells around them. Platforms are indexed
+/// in every cell they overlap.
+/// All fixed bodies must be registered here to be collided against.
+/// Lookups are by cell, in row order, so queries stay deterministic.
+/// Cells whose brick has broken, and not grown back or been replaced, are
+/// remembered so the AI can steer clear of the holes.
+#[derive(Default)]
+pub struct BrickGrid {
+    cells: HashMap<Cell, Entity>,
+    platforms: HashMap<Cell, Vec<Entity>>,
+    broken: BTreeSet<Cell>,
 }
 
-pub fn handle_brick_damage(
-	mut query: Query<(&Durability, &mut DrawMode), Changed<Durability>>,
+impl BrickGrid {
+    pub fn cell_of(position: Vec2) -> Cell {
+        (
+            (position.x / CELL_SIZE).round() as i32,
+            (position.y / CELL_SIZE).round() as i32,
+        )
+    }
+
+    pub fn cell_center(cell: Cell) -> Vec2 {
+        Vec2::new(cell.0 as f32 * CELL_SIZE, cell.1 as f32 * CELL_SIZE)
+    }
+
+    pub fn insert(&mut self, position: Vec2, brick: Entity) {
+        let cell = BrickGrid::cell_of(position);
+        self.cells.insert(cell, brick);
+        self.broken.remove(&cell);
+    }
+
+    /// takes out a brick that has broken, leaving a hole in its cell
+    pub fn remove(&mut self, position: Vec2) {
+        let cell = BrickGrid::cell_of(position);
+        if self.cells.remove(&cell).is_some() {
+            self.broken.insert(cell);
+        }
+    }
+
+    pub fn is_broken(&self, cell: Cell) -> bool {
+        self.broken.contains(&cell)
+    }
+
+    /// every cell left empty by a broken brick, in row order
+    pub fn broken(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.broken.iter().copied()
+    }
+
+    pub fn insert_platform(&mut self, min: Vec2, max: Vec2, platform: Entity) {
+        let (min_cell, max_cell) = (BrickGrid::cell_of(min), BrickGrid::cell_of(max));
+        for y in min_cell.1..=max_cell.1 {
+            for x in min_cell.0..=max_cell.0 {
+                self.platforms.entry((x, y)).or_insert_with(Vec::new).push(platform);
+            }
+        }
+    }
+
+    pub fn get(&self, cell: Cell) -> Option<Entity> {
+        self.cells.get(&cell).copied()
+    }
+
+    /// bricks and platforms in every cell touching the area between `min` and `max`
+    pub fn query(&self, min: Vec2, max: Vec2) -> Vec<Entity> {
+        let (min_cell, max_cell) = (BrickGrid::cell_of(min), BrickGrid::cell_of(max));
+        let mut bodies = Vec::new();
+        for y in min_cell.1..=max_cell.1 {
+            for x in min_cell.0..=max_cell.0 {
+                if let Some(brick) = self.get((x, y)) {
+                    bodies.push(brick);
+                }
+                for platform in self.platforms.get(&(x, y)).into_iter().flatten() {
+                    if !bodies.contains(platform) {
+                        bodies.push(*platform);
+                    }
+                }
+            }
+        }
+        bodies
+    }
+
+    /// bricks in every cell within `radius` of `position`
+    pub fn query_around(&self, position: Vec2, radius: f32) -> Vec<Entity> {
+        self.query(position - Vec2::splat(radius), position + Vec2::splat(radius))
+    }
+
+    /// The middle of the nearest cell holding a brick or a platform, looking
+    /// out to `radius` one ring of cells at a time.
+    pub fn nearest_solid(&self, position: Vec2, radius: f32) -> Option<Vec2> {
+        let origin = BrickGrid::cell_of(position);
+        let rings = (radius / CELL_SIZE).ceil() as i32;
+        for ring in 0..=rings {
+            let mut nearest: Option<Vec2> = None;
+            for y in -ring..=ring {
+                for x in -ring..=ring {
+                    // only the cells on the edge of the ring are new
+                    if x.abs() != ring && y.abs() != ring {
+                        continue
+                    }
+                    let cell = (origin.0 + x, origin.1 + y);
+                    if self.get(cell).is_none() && !self.platforms.contains_key(&cell) {
+                        continue
+                    }
+                    let center = BrickGrid::cell_center(cell);
+                    if nearest.map_or(true, |nearest| center.distance_squared(position) < nearest.distance_squared(position)) {
+                        nearest = Some(center);
+                    }
+                }
+            }
+            if nearest.is_some() {
+                return nearest
+            }
+        }
+        None
+    }
+}
+
+pub fn setup(
+    mut active_level: ResMut<ActiveLevel>,
+    selection: Res<LevelSelection>,
+    asset_server: Res<AssetServer>,
 ) {
-	for (durability, mut draw_mode) in query.iter_mut() {
-        let durability = durability.0;
-        let maybe_colors = if 0. < durability && durability <= 200. {
-            Some((
-                Color::rgb(0.2, 0.2, 0.2),
-                Color::rgb(0.27, 0.0, 0.0),
-            ))
-        } else if 200. < durability && durability <= 400. {
-            Some((
-                Color::rgb(0.14, 0.1, 0.14),
-                Color::rgb(0.33, 0.0, 0.0),
-            ))
-        } else if 400. < durability && durability <= 600. {
-            Some((
-                Color::rgb(0.08, 0.08, 0.08),
-                Color::rgb(0.38, 0.0, 0.0),
-            ))
-        } else if 600. < durability && durability <= 800. {
-            Some((
-                Color::rgb(0.03, 0.04, 0.03),
-                Color::rgb(0.42, 0.0, 0.0),
-            ))
-        } else {
-            None
-        };
-        if let Some((fill, outline)) = maybe_colors {
-            *draw_mode = DrawMode::Outlined {
-                fill_mode: FillMode::color(fill),
-                outline_mode: StrokeMode::new(outline, 4.0),
+    *active_level = ActiveLevel {
+        handle: asset_server.load(selection.0.as_str()),
+        definition: None,
+    };
+}
+
+/// Interior geometry spawned from `PlatformDefinition`s.
+#[derive(Component)]
+pub struct Platform;
+
+/// Makes a brick solid: gives it the body its material calls for and, unless
+/// it is unbreakable, a full `Durability`. The brick still needs adding to the grid.
+pub fn solidify_brick(brick: &mut EntityCommands, brick_type: &BrickType) {
+    brick
+        .insert(FixedBody)
+        .insert(Collider::Cuboid(Vec2::splat(BRICK_APOTHEM)));
+    match brick_type.material {
+        BrickMaterial::Unbreakable => {}
+        BrickMaterial::Bouncy { restitution } => {
+            brick
+                .insert(Durability(brick_type.durability))
+                .insert(Restitution(restitution));
+        }
+        _ => {
+            brick.insert(Durability(brick_type.durability));
+        }
+    }
+}
+
+fn spawn_geometry(commands: &mut Commands, grid: &mut BrickGrid, definition: &LevelDefinition) {
+    *grid = BrickGrid::default();
+    for (cell, brick_type) in definition.brick_cells() {
+        let position = BrickGrid::cell_center(cell);
+        let mut brick = commands.spawn();
+        brick
+            .insert(brick_type.clone())
+            .insert(Transform::from_xyz(position.x, position.y, 0.));
+        solidify_brick(&mut brick, brick_type);
+        grid.insert(position, brick.id());
+    }
+
+    for platform in definition.platforms.iter() {
+        let (center, collider, one_way) = match platform {
+            PlatformDefinition::Block { center, half_extents } => {
+                (*center, Collider::Cuboid(*half_extents), false)
+            }
+            PlatformDefinition::OneWay { center, half_width } => {
+                (*center, Collider::Cuboid(Vec2::new(*half_width, ONE_WAY_THICKNESS / 2.)), true)
             }
+            PlatformDefinition::Slope { points } => {
+                let center = (points[0] + points[1] + points[2]) / 3.;
+                (center, Collider::Triangle([points[0] - center, points[1] - center, points[2] - center]), false)
+            }
+        };
+        let bounds = collider.bounds();
+        let mut entity = commands.spawn();
+        entity
+            .insert(Platform)
+            .insert(FixedBody)
+            .insert(collider)
+            .insert(Transform::from_xyz(center.x, center.y, 0.));
+        if one_way {
+            entity.insert(OneWay);
         }
-	}
+        grid.insert_platform(center - bounds, center + bounds, entity.id());
+    }
+}
+
+/// Spawns the level once it has loaded, and rebuilds its bricks whenever the
+/// file changes on disk.
+pub fn handle_level_spawn(
+    mut commands: Commands,
+    mut active_level: ResMut<ActiveLevel>,
+    mut grid: ResMut<BrickGrid>,
+    mut rng: ResMut<MatchRng>,
+    players: Res<player::PlayerCount>,
+    mut asset_events: EventReader<AssetEvent<LevelDefinition>>,
+    levels: Res<Assets<LevelDefinition>>,
+    geometry_query: Query<Entity, Or<(With<BrickType>, With<Platform>)>>,
+) {
+    let definition = match levels.get(&active_level.handle) {
+        Some(definition) => definition.clone(),
+        None => return,
+    };
+    if !active_level.is_spawned() {
+        spawn_geometry(&mut commands, &mut grid, &definition);
+        player::spawn_characters(&mut commands, &mut rng, &definition, players.0);
+        active_level.definition = Some(definition);
+        return
+    }
+    let was_modified = asset_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => handle == &active_level.handle,
+        _ => false,
+    });
+    if was_modified {
+        info!("reloading level layout");
+        for entity in geometry_query.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        spawn_geometry(&mut commands, &mut grid, &definition);
+        active_level.definition = Some(definition);
+    }
+}
+
+/// An explosive brick going off.
+#[derive(Debug)]
+pub struct BrickExplosion {
+    pub center: Vec2,
+    pub radius: f32,
+    pub damage: f32,
+    pub launch: f32,
+}
+
+/// A broken regenerating brick, counting down the seconds until it grows back.
+#[derive(Component)]
+pub struct Regrowing(pub f32);
+
+fn break_trigger(material: &BrickMaterial) -> audio::Trigger {
+    match material {
+        BrickMaterial::Bouncy { .. } => audio::Trigger::BouncyWallBreak,
+        BrickMaterial::Explosive { .. } => audio::Trigger::ExplosiveWallBreak,
+        BrickMaterial::Regenerating { .. } => audio::Trigger::RegeneratingWallBreak,
+        _ => audio::Trigger::WallBreak,
+    }
 }
 
 pub fn handle_brick_break(
     mut commands: Commands,
     mut writer: EventWriter<audio::Event>,
-    query: Query<(Entity, &Durability), Changed<Durability>>,
+    mut explosion_writer: EventWriter<BrickExplosion>,
+    mut grid: ResMut<BrickGrid>,
+    query: Query<(Entity, &Durability, &BrickType, &Transform), Changed<Durability>>,
+) {
+    for (brick, durability, brick_type, transform) in query.iter() {
+        if durability.0 > 0. {
+            continue
+        }
+        let position = transform.translation.truncate();
+        grid.remove(position);
+        match brick_type.material {
+            BrickMaterial::Regenerating { delay } => {
+                // the brick stays around, out of the way, until it grows back
+                commands.entity(brick)
+                    .remove::<Durability>()
+                    .remove::<FixedBody>()
+                    .remove::<Collider>()
+                    .insert(Regrowing(delay));
+            }
+            BrickMaterial::Explosive { radius, damage, launch } => {
+                explosion_writer.send(BrickExplosion {
+                    center: position,
+                    radius,
+                    damage,
+                    launch,
+                });
+                commands.entity(brick).despawn_recursive();
+            }
+            _ => {
+                commands.entity(brick).despawn_recursive();
+            }
+        }
+        writer.send(audio::Event(brick, break_trigger(&brick_type.material), audio::Offset(-1.)))
+    }
+}
+
+// Bricks caught in an explosion are only worn down here; any that break go off
+// on the next step, so chains of explosive bricks ripple outwards.
+pub fn handle_brick_explosion(
+    mut events: EventReader<BrickExplosion>,
+    grid: Res<BrickGrid>,
+    mut bricks_query: Query<(&mut Durability, &Transform)>,
+    mut characters_query: Query<(&Transform, &mut Velocity), With<DynamicBody>>,
+) {
+    for explosion in events.iter() {
+        for brick in grid.query_around(explosion.center, explosion.radius) {
+            if let Ok((mut durability, transform)) = bricks_query.get_mut(brick) {
+                if transform.translation.truncate().distance(explosion.center) <= explosion.radius {
+                    durability.0 -= explosion.damage;
+                }
+            }
+        }
+        for (transform, mut velocity) in characters_query.iter_mut() {
+            let offset = transform.translation.truncate() - explosion.center;
+            let distance = offset.length();
+            if distance > explosion.radius {
+                continue
+            }
+            // strongest right at the brick, fading out towards the edge of the blast
+            let direction = if distance > 0. { offset / distance } else { Vec2::Y };
+            let launch = direction * explosion.launch * (1. - distance / explosion.radius);
+            velocity.0 += launch.x;
+            velocity.1 += launch.y;
+        }
+    }
+}
+
+pub fn handle_brick_regrowth(
+    mut commands: Commands,
+    mut grid: ResMut<BrickGrid>,
+    mut query: Query<(Entity, &mut Regrowing, &BrickType, &Transform)>,
 ) {
-    for (brick, durability) in query.iter() {
-        if durability.0 <= 0. {
-            commands.entity(brick).despawn_recursive();
-            writer.send(audio::Event(brick, audio::Trigger::WallBreak, audio::Offset(-1.)))
+    for (brick, mut regrowing, brick_type, transform) in query.iter_mut() {
+        regrowing.0 -= player::TIME_STEP;
+        if regrowing.0 > 0. {
+            continue
         }
+        let mut brick_commands = commands.entity(brick);
+        brick_commands.remove::<Regrowing>();
+        solidify_brick(&mut brick_commands, brick_type);
+        grid.insert(transform.translation.truncate(), brick);
     }
 }
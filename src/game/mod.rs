@@ -1,132 +1,470 @@
-use bevy::{core::FixedTimestep, prelude::*};
+use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::ShapePlugin;
 
-use crate::{AppState, utils::destroy_recursive};
+use crate::{AppState, audio, utils::destroy_recursive};
 
+pub mod bindings;
+pub mod clock;
+pub mod collapse;
 mod gameover;
+pub mod geometry;
 pub mod input;
+mod join;
 pub mod level;
+mod pause;
+pub mod physics;
 pub mod player;
+pub mod presentation;
+pub mod replay;
+pub mod rng;
 pub mod scoreboard;
 
+// the activity a character takes on for `action`, if it's free to
+fn activity_for_action(
+    action: &input::ActionType,
+    activity: &player::Activity,
+    frame: &player::ActivityFrame,
+    jumps: &player::JumpCounter,
+    dodges: &player::DodgeCounter,
+    shield: &player::Shield,
+    move_set: &player::moves::ActiveMoveSet,
+) -> Option<player::Activity> {
+    // a counter or a shield can be cancelled into any other move
+    let can_execute_action = *activity == player::Activity::Idle
+        || *activity == player::Activity::Shield
+        || player::is_countering(move_set, activity, frame);
+    match action {
+        input::ActionType::Jump => {
+            player::can_jump(activity, jumps).then(|| player::Activity::Jump)
+        },
+        input::ActionType::Shield => {
+            (can_execute_action && shield.0 > 0.).then(|| player::Activity::Shield)
+        },
+        input::ActionType::Dodge => {
+            let can_dodge = can_execute_action || *activity == player::Activity::Jump;
+            (can_dodge && dodges.0 > 0).then(|| player::Activity::Dodge)
+        },
+        attack => {
+            if can_execute_action {
+                move_set.for_action(attack).map(player::Activity::Attack)
+            } else {
+                None
+            }
+        },
+    }
+}
+
 pub fn handle_input_events(
     mut events: EventReader<input::Event>,
-    mut action_query: Query<
-        (&mut player::InputInfluence, &mut player::Activity, &player::JumpCounter),
-        With<player::Player>,
-    >,
-    mut state: ResMut<State<AppState>>,
+    mut action_query: Query<(
+        &player::Player,
+        &mut player::InputInfluence,
+        &mut player::InputBuffer,
+        &mut player::Activity,
+        &player::ActivityFrame,
+        &player::JumpCounter,
+        &player::DodgeCounter,
+        &player::Shield,
+    )>,
+    move_set: Res<player::moves::ActiveMoveSet>,
+    buffer_settings: Res<player::InputBufferSettings>,
 ) {
-    if let Some((
-        mut influence,
-        mut activity,
-        jumps,
-    )) = action_query.get_single_mut().ok() {
-        for event in events.iter() {
-            match &event.0 {
-                input::Trigger::PlayerAction(action) => {
-                    let can_execute_action = *activity == player::Activity::Idle || *activity == player::Activity::Counter;
-                    let can_jump = jumps.0 > 0 && match activity.as_ref() {
-                        player::Activity::Jump | player::Activity::Flinch | player::Activity::Land(_) => false,
-                        _ => true,
-                    };
-                    match action {
-                        input::ActionType::Jump => {
-                            if can_jump {
-                                *activity = player::Activity::Jump;
-                            }
-                        },
-                        input::ActionType::Jab => {
-                            if can_execute_action {
-                                *activity = player::Activity::Jab;
-                            }
-                        },
-                        input::ActionType::Stomp => {
-                            if can_execute_action {
-                                *activity = player::Activity::Stomp;
-                            }
-                        },
-                        input::ActionType::Counter => {
-                            if can_execute_action {
-                                *activity = player::Activity::Counter;
-                            }
-                        },
-                        _ => {},
-                    }
-                },
-                input::Trigger::PlayerMovement(x, y) => {
-                    influence.0 = *x;
-                    influence.1 = *y;
-                },
-                input::Trigger::Pause => {
-                    // state.set(AppState::Menu).unwrap();
-                },
-            }
+    for input::Event(slot, trigger) in events.iter() {
+        // inputs from players that have been knocked out go nowhere
+        let (
+            _,
+            mut influence,
+            mut buffer,
+            mut activity,
+            frame,
+            jumps,
+            dodges,
+            shield,
+        ) = match action_query.iter_mut().find(|(player, ..)| player.0 == *slot) {
+            Some(character) => character,
+            None => continue,
+        };
+        match trigger {
+            input::Trigger::PlayerAction(action) => {
+                match activity_for_action(action, &activity, frame, jumps, dodges, shield, &move_set) {
+                    Some(next) => *activity = next,
+                    // pressed while the character was busy, so it waits to see if it frees up
+                    None => buffer.push(action.clone(), buffer_settings.steps),
+                }
+            },
+            input::Trigger::PlayerMovement(x, y) => {
+                influence.0 = *x;
+                influence.1 = *y;
+            },
         }
     }
 }
 
-pub struct GamePlugin;
+/// Performs a buffered action as soon as the character is free to, the one
+/// with the highest priority if several are waiting, and forgets presses that
+/// have waited too long.
+pub fn handle_input_buffer(
+    mut query: Query<(
+        &mut player::InputBuffer,
+        &mut player::Activity,
+        &player::ActivityFrame,
+        &player::JumpCounter,
+        &player::DodgeCounter,
+        &player::Shield,
+    )>,
+    move_set: Res<player::moves::ActiveMoveSet>,
+) {
+    for (mut buffer, mut activity, frame, jumps, dodges, shield) in query.iter_mut() {
+        if buffer.0.is_empty() {
+            continue
+        }
+        // of equal priorities, the latest press wins
+        let next = buffer.0.iter()
+            .filter_map(|(action, _)| {
+                activity_for_action(action, &activity, frame, jumps, dodges, shield, &move_set)
+                    .map(|next| (action.priority(), next))
+            })
+            .max_by_key(|(priority, _)| *priority);
+        if let Some((_, next)) = next {
+            *activity = next;
+            // the other presses were meant for the same opening
+            buffer.0.clear();
+            continue
+        }
+        for (_, steps) in buffer.0.iter_mut() {
+            *steps = steps.saturating_sub(1);
+        }
+        buffer.0.retain(|(_, steps)| *steps > 0);
+    }
+}
 
-impl Plugin for GamePlugin {
+/// The gameplay rules: characters, bricks, AI and scoring, all running on plain
+/// components. This plugin needs nothing beyond `MinimalPlugins`, `AssetPlugin`
+/// for level files and an `AppState` state, so it can run a match without a window.
+///
+/// Every gameplay system runs on the `SimulationClock` and draws randomness
+/// only from `MatchRng`, so a match replays bit-for-bit from its `MatchSeed`
+/// and input stream, which `tests/determinism.rs` checks. Insert either
+/// resource before adding the plugin to override its default.
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
 	fn build(&self, app: &mut App) {
 		app
-            .add_plugin(ShapePlugin)
 			.insert_resource(scoreboard::Score::default())
 			.insert_resource(player::EnemySpawnTimer::default())
+			.init_resource::<player::PlayerCount>()
+			.init_resource::<player::Eliminations>()
+			.init_resource::<player::InputBufferSettings>()
+			.init_resource::<player::ai::AiDifficulty>()
+			.init_resource::<player::ai::AiSettingsOverride>()
+			.init_resource::<player::ai::FlockingSettings>()
+			.init_resource::<level::BrickGrid>()
+			.init_resource::<collapse::CollapseDirector>()
+			.init_resource::<collapse::CollapseSettings>()
+			.init_resource::<level::LevelSelection>()
+			.init_resource::<level::ActiveLevel>()
+			.init_resource::<player::moves::ActiveMoveSet>()
+			.add_asset::<level::LevelDefinition>()
+			.add_asset::<player::moves::MoveSet>()
+			.init_asset_loader::<level::LevelLoader>()
+			.init_asset_loader::<player::moves::MoveSetLoader>()
+			.init_resource::<clock::SimulationClock>()
+			.init_resource::<rng::MatchSeed>()
+			.init_resource::<rng::MatchRng>()
+			.init_resource::<replay::ReplayRecorder>()
 			.add_event::<input::Event>()
+			.add_event::<audio::Event>()
+			.add_event::<physics::ContactEvent>()
+			.add_event::<level::BrickExplosion>()
 			.add_system_set(
 				SystemSet::on_enter(AppState::Game)
+					.with_system(clock::reset_clock)
+					.with_system(rng::reseed_match_rng)
+					.with_system(replay::start_recording)
+					.with_system(collapse::reset_director)
 					.with_system(level::setup)
+					.with_system(player::moves::setup)
 					.with_system(player::setup)
-                    .with_system(scoreboard::setup)
 			)
 			.add_system_set(
 				SystemSet::on_update(AppState::Game)
-					.with_run_criteria(FixedTimestep::step(player::TIME_STEP as f64))
-					.with_system(input::handle_gamepad_input.label("handle_gamepad_input"))
-					.with_system(input::handle_keyboard_input.label("handle_keyboard_input").after("handle_gamepad_input"))
-                    .with_system(player::ai::handle_ai_behavior)
-                    .with_system(player::ai::handle_ai_input.label("handle_ai_input"))
+					.with_system(level::handle_level_spawn)
+					.with_system(player::moves::handle_move_set_load)
+			)
+			.add_system_set(
+				SystemSet::new()
+					.with_run_criteria(clock::run_simulation_step.label(clock::SIMULATION_STEP))
+                    .with_system(replay::play_replay_events.label("read_input"))
+                    .with_system(replay::record_input_events.after("read_input"))
+                    .with_system(
+                        player::ai::handle_ai_behavior::<player::Enemy, player::Player>
+                            .label("handle_ai_behavior")
+                    )
+                    .with_system(
+                        player::ai::handle_ai_input::<player::Enemy, player::Player>
+                            .label("handle_ai_input")
+                            .after("handle_ai_behavior")
+                    )
+                    .with_system(
+                        player::ai::handle_ai_roles::<player::Enemy, player::Player>
+                            .label("handle_ai_roles")
+                            .after("handle_ai_behavior")
+                    )
+                    .with_system(
+                        player::ai::handle_ai_flocking::<player::Enemy, player::Player>
+                            .label("handle_ai_flocking")
+                            .after("handle_ai_input")
+                            .after("handle_ai_roles")
+                    )
+                    // AI players read what enemies have just done and share the match RNG
+                    // with them, so they only start once the enemies are done
+                    .with_system(
+                        player::ai::handle_ai_behavior::<player::ai::AiPlayer, player::Enemy>
+                            .label("handle_ai_player_behavior")
+                            .after("handle_ai_flocking")
+                    )
+                    .with_system(
+                        player::ai::handle_ai_input::<player::ai::AiPlayer, player::Enemy>
+                            .label("handle_ai_player_input")
+                            .after("handle_ai_player_behavior")
+                    )
+                    .with_system(
+                        player::ai::handle_ai_roles::<player::ai::AiPlayer, player::Enemy>
+                            .label("handle_ai_player_roles")
+                            .after("handle_ai_player_behavior")
+                    )
+                    .with_system(
+                        player::ai::handle_ai_flocking::<player::ai::AiPlayer, player::Enemy>
+                            .label("handle_ai_player_flocking")
+                            .after("handle_ai_player_input")
+                            .after("handle_ai_player_roles")
+                    )
                     .with_system(
                         handle_input_events
                             .label("handle_input_events")
-                            .after("handle_gamepad_input")
-                            .after("handle_keyboard_input")
-                            .after("handle_ai_input")
+                            .after("read_input")
+                            .after("handle_ai_player_flocking")
+                    )
+                    .with_system(
+                        handle_input_buffer
+                            .label("handle_input_buffer")
+                            .after("handle_input_events")
+                    )
+                    .with_system(
+                        player::handle_hit_stop
+                            .label("handle_hit_stop")
+                            .after("handle_input_buffer")
                     )
 					.with_system(
                         player::handle_activity_change
                             .label("handle_activity_change")
-                            .after("handle_input_events")
+                            .after("handle_hit_stop")
                     )
 					.with_system(
                         player::handle_activity_timer
                             .label("handle_activity_timer")
-                            .after("handle_activity_change")) // TODO: make sure to add ordering dependencies
+                            .after("handle_activity_change")
+                    )
                     .with_system(
-                        player::handle_physics
+                        player::handle_movement
+                            .label("handle_movement")
+                            .after("handle_activity_timer")
+                    )
+                    .with_system(
+                        player::projectile::handle_projectile_spawn
+                            .label("handle_projectile_spawn")
+                            .after("handle_activity_timer")
+                    )
+                    .with_system(
+                        physics::step_physics
                             .label("physics")
-                            .after("handle_activity_change")
+                            .after("handle_movement")
+                            .after("handle_projectile_spawn")
+                    )
+                    // systems that touch the same components run in a fixed order,
+                    // otherwise the executor is free to pick one and replays drift
+                    .with_system(player::handle_contacts.label("handle_contacts").after("physics"))
+                    .with_system(
+                        player::hitbox::handle_hitbox_lifetime
+                            .label("handle_hitbox_lifetime")
+                            .after("handle_contacts")
+                    )
+                    .with_system(
+                        player::handle_attack_collision
+                            .label("handle_attack_collision")
+                            .after("handle_hitbox_lifetime")
+                    )
+                    .with_system(
+                        player::projectile::handle_projectiles
+                            .label("handle_projectiles")
+                            .after("handle_attack_collision")
+                    )
+                    .with_system(
+                        player::handle_holds
+                            .label("handle_holds")
+                            .after("handle_projectiles")
+                    )
+                    .with_system(
+                        player::handle_shield
+                            .label("handle_shield")
+                            .after("handle_holds")
+                    )
+                    .with_system(
+                        player::handle_turning
+                            .label("handle_turning")
+                            .after("handle_shield")
+                    )
+					.with_system(
+                        player::handle_enemy_spawn_timer
+                            .label("handle_enemy_spawn_timer")
+                            .after("handle_ai_player_input")
+                    )
+                    .with_system(
+                        collapse::handle_collapse_director
+                            .label("collapse")
+                            .after("handle_contacts")
+                            .after("handle_projectiles")
+                    )
+                    .with_system(
+                        level::handle_brick_break
+                            .label("handle_brick_break")
+                            .after("collapse")
+                    )
+                    .with_system(
+                        level::handle_brick_explosion
+                            .label("handle_brick_explosion")
+                            .after("handle_brick_break")
+                            .after("handle_turning")
+                    )
+                    .with_system(
+                        level::handle_brick_regrowth
+                            .label("handle_brick_regrowth")
+                            .after("handle_brick_explosion")
+                    )
+					.with_system(
+                        player::detect_enemy_death_system
+                            .label("detect_enemy_death_system")
+                            .after("handle_turning")
+                    )
+                    .with_system(
+                        player::handle_status_tick
+                            .label("handle_status_tick")
+                            .after("detect_enemy_death_system")
+                    )
+					.with_system(
+                        player::detect_gameover_system
+                            .label("detect_gameover_system")
+                            .after("handle_status_tick")
                     )
-                    .with_system(player::handle_attack_collision.after("physics"))
-                    .with_system(player::handle_turning.after("physics"))
-					.with_system(player::handle_enemy_spawn_timer)
-                    .with_system(level::handle_brick_damage.after("physics"))
-                    .with_system(level::handle_brick_break.after("physics"))
-					.with_system(player::detect_enemy_death_system.after("physics"))
-                    .with_system(player::handle_status_tick.label("handle_status_tick").after("physics"))
-                    .with_system(player::handle_status_change.after("handle_status_tick").before("detect_gameover_system"))
-                    .with_system(scoreboard::handle_tracking_score.before("detect_gameover_system"))
-					.with_system(player::detect_gameover_system.label("detect_gameover_system").after("physics"))
 			)
 			.add_system_set(
 				SystemSet::on_exit(AppState::Game)
-					.with_system(destroy_recursive::<scoreboard::ScoreboardRootNode>)
+                    .with_system(replay::save_replay)
                     .with_system(destroy_recursive::<player::Player>)
 					.with_system(destroy_recursive::<player::Enemy>)
-					.with_system(destroy_recursive::<level::Durability>)
+					.with_system(destroy_recursive::<player::hitbox::Hitbox>)
+					.with_system(destroy_recursive::<player::projectile::Projectile>)
+					.with_system(destroy_recursive::<level::BrickType>)
+					.with_system(destroy_recursive::<level::Platform>)
+			);
+	}
+}
+
+/// The full game: the simulation, plus device input, shapes, HUD and menus
+/// layered on top of it.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+	fn build(&self, app: &mut App) {
+		app
+            .add_plugin(ShapePlugin)
+            .add_plugin(SimulationPlugin)
+            .init_resource::<input::Roster>()
+            .init_resource::<input::SentMovement>()
+            .init_resource::<bindings::Bindings>()
+            .init_resource::<pause::Rebinding>()
+            .add_system_set(
+                SystemSet::on_enter(AppState::Join)
+                    .with_system(join::setup)
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Join)
+                    .with_system(join::handle_join_input)
+                    .with_system(join::handle_ui)
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Join)
+                    .with_system(destroy_recursive::<join::JoinRootNode>)
+            )
+			.add_system_set(
+				SystemSet::on_enter(AppState::Game)
+                    .with_system(scoreboard::setup)
+                    .with_system(scoreboard::setup_damage)
+                    .with_system(input::reset_sent_movement)
 			)
+			.add_system_set(
+				SystemSet::new()
+					// a replay stands in for the devices while it plays
+					.with_run_criteria(RunCriteria::pipe(
+						clock::SIMULATION_STEP,
+						replay::skip_while_replaying.system(),
+					))
+					.with_system(
+                        input::handle_gamepad_input
+                            .label("handle_gamepad_input")
+                            .label("read_input")
+                    )
+					.with_system(
+                        input::handle_keyboard_input
+                            .label("handle_keyboard_input")
+                            .label("read_input")
+                            .after("handle_gamepad_input")
+                    )
+			)
+			.init_resource::<presentation::DebugOverlay>()
+			.add_system(presentation::toggle_debug_overlay)
+			.add_system_set(
+				SystemSet::on_update(AppState::Game)
+                    .with_system(presentation::handle_activity_shape)
+                    .with_system(presentation::handle_brick_damage)
+                    .with_system(presentation::handle_collapse_warning)
+                    .with_system(presentation::handle_brick_regrowth)
+                    .with_system(presentation::handle_status_change)
+                    .with_system(presentation::handle_debug_overlay)
+                    .with_system(scoreboard::handle_tracking_score)
+                    .with_system(scoreboard::handle_tracking_damage)
+                    .with_system(input::handle_pause_input)
+			)
+            // shapes are attached after the simulation has moved everything for this frame
+            .add_system_to_stage(CoreStage::PostUpdate, presentation::attach_character_shapes)
+            .add_system_to_stage(CoreStage::PostUpdate, presentation::attach_brick_shapes)
+            .add_system_to_stage(CoreStage::PostUpdate, presentation::attach_platform_shapes)
+            .add_system_to_stage(CoreStage::PostUpdate, presentation::attach_hitbox_shapes)
+            .add_system_to_stage(CoreStage::PostUpdate, presentation::attach_projectile_shapes)
+            .add_system_to_stage(CoreStage::PostUpdate, presentation::attach_hurtbox_shapes)
+			.add_system_set(
+				SystemSet::on_exit(AppState::Game)
+					.with_system(destroy_recursive::<scoreboard::ScoreboardRootNode>)
+			)
+            // the match stays where it is underneath the pause menu
+            .add_system_set(
+                SystemSet::on_enter(AppState::Paused)
+                    .with_system(pause::setup)
+                    .with_system(input::clear_just_pressed)
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Paused)
+                    .with_system(pause::handle_ui)
+                    .with_system(pause::handle_rebinding_input)
+                    .with_system(pause::handle_binding_text)
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Paused)
+                    .with_system(destroy_recursive::<pause::PauseRootNode>)
+                    .with_system(bindings::save_bindings)
+                    .with_system(input::clear_just_pressed)
+            )
             .add_system_set(
                 SystemSet::on_enter(AppState::GameOver)
                     .with_system(gameover::setup)
@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+
+use crate::{
+    AppState,
+    game::{
+        bindings::{Bindings, Control},
+        input::is_pause_pressed,
+    },
+};
+
+// Pausing stops the simulation clock and lists every control with what it's
+// bound to. Clicking a control waits for a key or button: a new one is added
+// to its bindings, and one it already had is taken off.
+
+#[derive(Component)]
+pub struct PauseRootNode;
+/// The button listing the bindings of a control.
+#[derive(Component)]
+pub struct BindingButton(pub Control);
+#[derive(Component)]
+pub enum MenuButton {
+    Resume,
+    ResetBindings,
+}
+
+/// The control waiting for a key or button to be bound to it, if any.
+#[derive(Default)]
+pub struct Rebinding(pub Option<Control>);
+
+const BACKGROUND: Color = Color::rgba(0.1, 0.2, 0.45, 0.85);
+const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+fn binding_label(control: Control, bindings: &Bindings, rebinding: &Rebinding) -> String {
+    if rebinding.0 == Some(control) {
+        return format!("{:?}: press a key or button...", control)
+    }
+    let keys: Vec<String> = bindings.keys(control).iter().map(|key| format!("{:?}", key)).collect();
+    let buttons: Vec<String> = bindings.buttons(control).iter().map(|button| format!("{:?}", button)).collect();
+    format!("{:?}: {} / {}", control, keys.join(", "), buttons.join(", "))
+}
+
+fn spawn_button(parent: &mut ChildBuilder, width: f32, text: String, font: Handle<Font>, marker: impl Component) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(width), Val::Px(32.0)),
+                margin: Rect::all(Val::Px(2.0)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: NORMAL_BUTTON.into(),
+            ..Default::default()
+        })
+        .insert(marker)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    text,
+                    TextStyle {
+                        font,
+                        font_size: 24.0,
+                        color: TEXT_COLOR,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+pub fn setup(
+    mut commands: Commands,
+    bindings: Res<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    rebinding.0 = None;
+
+    commands
+        .spawn()
+        .insert(PauseRootNode)
+        .insert_bundle(NodeBundle {
+            color: BACKGROUND.into(),
+            style: Style {
+                // drawn over the match rather than next to the scoreboard
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "Paused".to_string(),
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 40.0,
+                        color: TEXT_COLOR,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+            for control in Control::ALL {
+                let label = binding_label(control, &bindings, &rebinding);
+                spawn_button(parent, 560., label, font.clone(), BindingButton(control));
+            }
+            spawn_button(parent, 260., "Resume".to_string(), font.clone(), MenuButton::Resume);
+            spawn_button(parent, 260., "Reset bindings".to_string(), font, MenuButton::ResetBindings);
+        });
+}
+
+pub fn handle_ui(
+    mut state: ResMut<State<AppState>>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    mut interaction_query: Query<
+        (&Interaction, &mut UiColor, Option<&BindingButton>, Option<&MenuButton>),
+        (Changed<Interaction>, With<Button>),
+    >,
+) {
+    for (interaction, mut color, binding, menu) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Clicked => {
+                *color = PRESSED_BUTTON.into();
+                if let Some(BindingButton(control)) = binding {
+                    rebinding.0 = Some(*control);
+                }
+                match menu {
+                    Some(MenuButton::Resume) => {
+                        let _ = state.pop();
+                    }
+                    Some(MenuButton::ResetBindings) => {
+                        *bindings = Bindings::default();
+                        rebinding.0 = None;
+                    }
+                    None => {}
+                }
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_BUTTON.into();
+            }
+            Interaction::None => {
+                *color = NORMAL_BUTTON.into();
+            }
+        }
+    }
+}
+
+/// Binds the next key or button pressed to the control waiting for one, and
+/// otherwise resumes the match when pause is pressed again.
+pub fn handle_rebinding_input(
+    mut state: ResMut<State<AppState>>,
+    mut bindings: ResMut<Bindings>,
+    mut rebinding: ResMut<Rebinding>,
+    keys: Res<Input<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+) {
+    match rebinding.0 {
+        Some(control) => {
+            if let Some(key) = keys.get_just_pressed().next() {
+                bindings.toggle_key(control, *key);
+                rebinding.0 = None;
+            } else if let Some(GamepadButton(_, button)) = button_inputs.get_just_pressed().next() {
+                bindings.toggle_button(control, *button);
+                rebinding.0 = None;
+            }
+        }
+        None => {
+            if is_pause_pressed(&bindings, &keys, &gamepads, &button_inputs) {
+                let _ = state.pop();
+            }
+        }
+    }
+}
+
+pub fn handle_binding_text(
+    bindings: Res<Bindings>,
+    rebinding: Res<Rebinding>,
+    button_query: Query<(&BindingButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return
+    }
+    for (button, children) in button_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = binding_label(button.0, &bindings, &rebinding);
+            }
+        }
+    }
+}
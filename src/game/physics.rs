@@ -0,0 +1,383 @@
+use bevy::prelude::*;
+use std::cmp::Ordering;
+
+use crate::game::{
+    geometry::cmp_colliders,
+    level::BrickGrid,
+    player::TIME_STEP,
+};
+
+pub use crate::game::geometry::Collider;
+
+// The original hand-written integrator, extended with contact reporting and
+// swept collisions. It is a stopgap, not the rigid-body backend the game is
+// meant to move to: that is still Rapier, with characters as dynamic balls,
+// bricks as fixed cuboids and its contact events driving wall damage and
+// landings, and its `enhanced-determinism` feature on so matches still replay
+// bit-for-bit. Gameplay only reaches the physics through `Velocity`,
+// `ContactEvent` and the body markers below, so the move stays contained here.
+//
+// Dynamic bodies are swept along their `Velocity` every step, stopping at the
+// first fixed body in their path and spending the rest of the step sliding
+// from there, so fast bodies can't tunnel through thin walls. Anything still
+// overlapping afterwards is pushed out. Every contact is reported as a
+// `ContactEvent`, which gameplay systems turn into landings and wall damage.
+
+#[derive(Component, Default)]
+pub struct Velocity(pub f32, pub f32);
+
+/// Moved by the physics step. Bodies have unit mass, so an impulse is the
+/// change in velocity it causes.
+#[derive(Component, Default)]
+pub struct DynamicBody;
+
+/// Never moved by the physics step, only collided against.
+#[derive(Component, Default)]
+pub struct FixedBody;
+
+/// Dynamic bodies with this are held where they are, keeping their velocity
+/// for when they're let go.
+#[derive(Component, Default)]
+pub struct Frozen;
+
+/// Fixed bodies with this only block bodies landing on them from above.
+#[derive(Component, Default)]
+pub struct OneWay;
+
+/// How much of a body's approach speed a fixed body sends back: 0 stops it
+/// dead, 1 bounces it off at full speed. Fixed bodies without one don't bounce.
+#[derive(Component, Clone, Copy, Default)]
+pub struct Restitution(pub f32);
+
+/// A dynamic body touching a fixed one during the last step.
+#[derive(Debug)]
+pub struct ContactEvent {
+    pub body: Entity,
+    pub other: Entity,
+    /// points out of `other`, towards `body`
+    pub normal: Vec2,
+    /// the impulse along `normal` that stopped `body` going through `other`
+    pub impulse: f32,
+}
+
+struct Sweep {
+    /// how far along the swept motion the bodies first touch, from 0 to 1
+    time: f32,
+    normal: Vec2,
+}
+
+// entry and exit times of a moving point across the slab between `min` and `max`
+fn sweep_slab(start: f32, delta: f32, min: f32, max: f32) -> Option<(f32, f32)> {
+    if delta.abs() < f32::EPSILON {
+        if start < min || start > max {
+            None
+        } else {
+            Some((f32::NEG_INFINITY, f32::INFINITY))
+        }
+    } else {
+        let t1 = (min - start) / delta;
+        let t2 = (max - start) / delta;
+        Some(if t1 < t2 { (t1, t2) } else { (t2, t1) })
+    }
+}
+
+// first time a ball moving by `delta` touches a cuboid, if it does so this step
+// and isn't touching it already
+fn sweep_ball_and_cuboid(
+    start: Vec2,
+    delta: Vec2,
+    radius: f32,
+    cuboid_center: Vec2,
+    half_extents: Vec2,
+) -> Option<Sweep> {
+    // sweep the ball's center against the cuboid grown by the radius...
+    let expanded = half_extents + Vec2::splat(radius);
+    let (enter_x, exit_x) = sweep_slab(start.x, delta.x, cuboid_center.x - expanded.x, cuboid_center.x + expanded.x)?;
+    let (enter_y, exit_y) = sweep_slab(start.y, delta.y, cuboid_center.y - expanded.y, cuboid_center.y + expanded.y)?;
+    let (time, normal) = if enter_x > enter_y {
+        (enter_x, Vec2::new(-delta.x.signum(), 0.))
+    } else {
+        (enter_y, Vec2::new(0., -delta.y.signum()))
+    };
+    let exit = exit_x.min(exit_y);
+    if time > exit || time > 1. || exit < 0. {
+        return None
+    }
+
+    // ...whose corners are really rounded, so hits there are against a circle
+    let local_hit = start + delta * time.max(0.) - cuboid_center;
+    if local_hit.x.abs() <= half_extents.x || local_hit.y.abs() <= half_extents.y {
+        // starting inside a face is an overlap, not a sweep
+        return if time >= 0. { Some(Sweep { time, normal }) } else { None }
+    }
+    let corner = cuboid_center + half_extents * local_hit.signum();
+    let from_corner = start - corner;
+    let a = delta.length_squared();
+    let b = 2. * from_corner.dot(delta);
+    let c = from_corner.length_squared() - radius * radius;
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return None
+    }
+    let time = (-b - discriminant.sqrt()) / (2. * a);
+    if time < 0. || time > 1. {
+        return None
+    }
+    Some(Sweep {
+        time,
+        normal: (start + delta * time - corner) / radius,
+    })
+}
+
+// for shapes without an exact sweep, test along the motion in steps small
+// enough that the body can't skip over anything thicker than half its size
+fn sweep_by_sampling(
+    body: (&Collider, Vec2),
+    delta: Vec2,
+    other: (&Collider, Vec2),
+) -> Option<Sweep> {
+    let (collider, start) = body;
+    // already touching is an overlap, not a sweep
+    if cmp_colliders(body, other).is_some() {
+        return None
+    }
+    let spacing = collider.bounds().min_element() / 2.;
+    let samples = (delta.length() / spacing).ceil().max(1.) as usize;
+    (1..=samples).find_map(|sample| {
+        let time = sample as f32 / samples as f32;
+        cmp_colliders((collider, start + delta * time), other)
+            .map(|contact| Sweep {
+                // back off to where the body was only just touching
+                time: (time - contact.depth / delta.length().max(f32::EPSILON)).max(0.),
+                normal: contact.normal,
+            })
+    })
+}
+
+fn sweep_colliders(
+    body: (&Collider, Vec2),
+    delta: Vec2,
+    other: (&Collider, Vec2),
+) -> Option<Sweep> {
+    match (body, other) {
+        ((Collider::Ball(radius), start), (Collider::Cuboid(half_extents), cuboid_center)) => {
+            sweep_ball_and_cuboid(start, delta, *radius, cuboid_center, *half_extents)
+        }
+        _ => sweep_by_sampling(body, delta, other),
+    }
+}
+
+// how many impacts a body may resolve in one step before its motion is dropped
+const MAX_SUBSTEPS: usize = 4;
+// the gap left between bodies stopped by a sweep, so they don't start the next one touching
+const CONTACT_SKIN: f32 = 0.01;
+
+// how upright a surface normal must be for a one-way platform to hold a body up
+const ONE_WAY_MIN_NORMAL_Y: f32 = 0.7;
+
+struct FixedShape {
+    entity: Entity,
+    collider: Collider,
+    center: Vec2,
+    one_way: bool,
+    restitution: f32,
+}
+
+impl FixedShape {
+    // one-way platforms only block bodies coming down onto their top face
+    fn blocks(&self, normal: Vec2, velocity: &Velocity, depth: f32) -> bool {
+        !self.one_way || (
+            normal.y > ONE_WAY_MIN_NORMAL_Y
+                && velocity.1 <= 0.
+                && depth <= self.collider.bounds().y
+        )
+    }
+}
+
+// remove any velocity into the surface, sending `restitution` of it back out,
+// and return the impulse that took
+fn cancel_approach(velocity: &mut Velocity, normal: Vec2, restitution: f32) -> f32 {
+    let approach = Vec2::new(velocity.0, velocity.1).dot(normal);
+    let impulse = if approach < 0. { -approach * (1. + restitution) } else { 0. };
+    velocity.0 += normal.x * impulse;
+    velocity.1 += normal.y * impulse;
+    impulse
+}
+
+pub fn step_physics(
+    mut dynamic_query: Query<
+        (Entity, &Collider, &mut Transform, &mut Velocity),
+        (With<DynamicBody>, Without<Frozen>),
+    >,
+    fixed_query: Query<
+        (Entity, &Collider, &Transform, Option<&OneWay>, Option<&Restitution>),
+        (With<FixedBody>, Without<DynamicBody>),
+    >,
+    grid: Res<BrickGrid>,
+    mut writer: EventWriter<ContactEvent>,
+) {
+    for (body, collider, mut transform, mut velocity) in dynamic_query.iter_mut() {
+        // only fixed bodies in cells the body could reach this step are considered
+        let start = transform.translation.truncate();
+        let end = start + Vec2::new(velocity.0, velocity.1) * TIME_STEP;
+        let reach = collider.bounds();
+        let fixed_bodies: Vec<FixedShape> = grid.query(start.min(end) - reach, start.max(end) + reach)
+            .into_iter()
+            .filter_map(|entity| fixed_query.get(entity).ok())
+            .map(|(entity, collider, transform, one_way, restitution)| FixedShape {
+                entity,
+                collider: collider.clone(),
+                center: transform.translation.truncate(),
+                one_way: one_way.is_some(),
+                restitution: restitution.map_or(0., |restitution| restitution.0),
+            })
+            .collect();
+
+        // advance from impact to impact until the step is used up
+        let mut remaining = TIME_STEP;
+        for _ in 0..MAX_SUBSTEPS {
+            let start = transform.translation.truncate();
+            let delta = Vec2::new(velocity.0, velocity.1) * remaining;
+            let earliest_hit = fixed_bodies.iter()
+                .filter_map(|other| {
+                    sweep_colliders((collider, start), delta, (&other.collider, other.center))
+                        .filter(|sweep| other.blocks(sweep.normal, &velocity, 0.))
+                        .map(|sweep| (other, sweep))
+                })
+                .min_by(|(_, a), (_, b)| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+
+            if let Some((other, sweep)) = earliest_hit {
+                let stop = start + delta * sweep.time + sweep.normal * CONTACT_SKIN;
+                transform.translation.x = stop.x;
+                transform.translation.y = stop.y;
+                let impulse = cancel_approach(&mut velocity, sweep.normal, other.restitution);
+                writer.send(ContactEvent {
+                    body,
+                    other: other.entity,
+                    normal: sweep.normal,
+                    impulse,
+                });
+                remaining *= 1. - sweep.time;
+            } else {
+                transform.translation.x += delta.x;
+                transform.translation.y += delta.y;
+                break
+            }
+        }
+
+        for other in fixed_bodies.iter() {
+            let contact = cmp_colliders(
+                (collider, transform.translation.truncate()),
+                (&other.collider, other.center),
+            );
+            if let Some(contact) = contact.filter(|contact| other.blocks(contact.normal, &velocity, contact.depth)) {
+                // separate the bodies, then cancel any velocity into the fixed body
+                transform.translation.x += contact.normal.x * contact.depth;
+                transform.translation.y += contact.normal.y * contact.depth;
+                let impulse = cancel_approach(&mut velocity, contact.normal, other.restitution);
+                writer.send(ContactEvent {
+                    body,
+                    other: other.entity,
+                    normal: contact.normal,
+                    impulse,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::app::Events;
+
+    use crate::game::{
+        level::{BRICK_APOTHEM, CELL_SIZE},
+        player::PLAYER_RADIUS,
+    };
+
+    // four cells a step: faster than any launch, and far enough that checking
+    // only where it ends up would skip a one-brick wall entirely
+    const LAUNCH_SPEED: f32 = CELL_SIZE * 4. / TIME_STEP;
+    const WALL_X: f32 = CELL_SIZE * 6.;
+
+    #[test]
+    fn fast_ball_sweeps_into_a_single_brick() {
+        let delta = Vec2::new(CELL_SIZE * 5., 0.);
+        let brick = Vec2::new(CELL_SIZE * 3., 0.);
+        let sweep = sweep_ball_and_cuboid(Vec2::ZERO, delta, PLAYER_RADIUS, brick, Vec2::splat(BRICK_APOTHEM))
+            .expect("the ball went through the brick");
+        assert!(sweep.time > 0. && sweep.time < 1., "time of impact {}", sweep.time);
+        let expected = (brick.x - BRICK_APOTHEM - PLAYER_RADIUS) / delta.x;
+        assert!((sweep.time - expected).abs() < 1e-5);
+        assert_eq!(sweep.normal, Vec2::new(-1., 0.));
+    }
+
+    #[test]
+    fn fast_ball_sweeps_onto_a_corner() {
+        // grazes the top left corner of the brick on the way past
+        let brick = Vec2::new(CELL_SIZE * 3., 0.);
+        let start = Vec2::new(0., BRICK_APOTHEM + PLAYER_RADIUS / 2.);
+        let sweep = sweep_ball_and_cuboid(start, Vec2::new(CELL_SIZE * 5., 0.), PLAYER_RADIUS, brick, Vec2::splat(BRICK_APOTHEM))
+            .expect("the ball missed the corner");
+        assert!(sweep.time > 0. && sweep.time < 1.);
+        assert!(sweep.normal.x < 0. && sweep.normal.y > 0.);
+        assert!((sweep.normal.length() - 1.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn sweep_misses_a_brick_out_of_reach() {
+        let brick = Vec2::new(CELL_SIZE * 3., 0.);
+        assert!(sweep_ball_and_cuboid(Vec2::ZERO, Vec2::new(CELL_SIZE, 0.), PLAYER_RADIUS, brick, Vec2::splat(BRICK_APOTHEM)).is_none());
+        let above = Vec2::new(0., BRICK_APOTHEM + PLAYER_RADIUS + 1.);
+        assert!(sweep_ball_and_cuboid(above, Vec2::new(CELL_SIZE * 5., 0.), PLAYER_RADIUS, brick, Vec2::splat(BRICK_APOTHEM)).is_none());
+    }
+
+    #[test]
+    fn fast_ball_samples_into_a_thin_slope() {
+        let slope = Collider::Triangle([Vec2::new(-20., -40.), Vec2::new(20., -40.), Vec2::new(-20., 40.)]);
+        let at = Vec2::new(CELL_SIZE * 3., 0.);
+        let sweep = sweep_by_sampling((&Collider::Ball(PLAYER_RADIUS), Vec2::ZERO), Vec2::new(CELL_SIZE * 5., 0.), (&slope, at))
+            .expect("the ball went through the slope");
+        assert!(sweep.time > 0. && sweep.time < 1., "time of impact {}", sweep.time);
+        assert!(sweep.normal.x < -0.9);
+    }
+
+    #[test]
+    fn launched_character_hits_the_wall_instead_of_leaving() {
+        let mut world = World::new();
+        world.insert_resource(Events::<ContactEvent>::default());
+        let mut grid = BrickGrid::default();
+        for row in -4..=4 {
+            let position = Vec2::new(WALL_X, row as f32 * CELL_SIZE);
+            let brick = world.spawn()
+                .insert(FixedBody)
+                .insert(Collider::Cuboid(Vec2::splat(BRICK_APOTHEM)))
+                .insert(Transform::from_xyz(position.x, position.y, 0.))
+                .id();
+            grid.insert(position, brick);
+        }
+        world.insert_resource(grid);
+        let character = world.spawn()
+            .insert(DynamicBody)
+            .insert(Collider::Ball(PLAYER_RADIUS))
+            .insert(Transform::default())
+            .insert(Velocity(LAUNCH_SPEED, 0.))
+            .id();
+
+        let mut stage = SystemStage::single_threaded().with_system(step_physics);
+        for _ in 0..10 {
+            stage.run(&mut world);
+            let x = world.get::<Transform>(character).unwrap().translation.x;
+            assert!(x <= WALL_X - BRICK_APOTHEM - PLAYER_RADIUS + 1e-3, "the character passed the wall to {}", x);
+        }
+
+        let events = world.get_resource::<Events<ContactEvent>>().unwrap();
+        let contact = events.get_reader().iter(events)
+            .find(|contact| contact.body == character)
+            .expect("the character never touched the wall");
+        assert!(contact.impulse > 0.);
+        assert_eq!(contact.normal, Vec2::new(-1., 0.));
+        assert!(world.get::<Velocity>(character).unwrap().0 <= 0.);
+    }
+}
@@ -1,18 +1,28 @@
 use bevy::prelude::*;
-use bevy_prototype_lyon::{entity::Path, prelude::*};
-use std::f32::consts::PI;
+use rand::Rng;
+use std::{f32::consts::PI, ops::Range};
 
 use crate::{
     AppState,
     audio,
     game::{
-        level::{Durability, BRICK_APOTHEM, LEVEL_HEIGHT, LEVEL_WIDTH},
+        input::ActionType,
+        level::{ActiveLevel, Bounds, Durability, LevelDefinition},
+        geometry,
+        physics::{Collider, ContactEvent, DynamicBody, Frozen, Velocity},
+        rng::MatchRng,
         scoreboard::Score,
     },
 };
 
 pub mod ai;
-pub mod collision;
+pub mod hitbox;
+pub mod moves;
+pub mod projectile;
+
+use hitbox::{Hitbox, Hurtbox};
+use moves::{ActiveMoveSet, MoveId};
+use projectile::Projectile;
 
 #[derive(Debug, PartialEq)]
 pub enum Direction {
@@ -22,8 +32,9 @@ pub enum Direction {
     Left,
 }
 
+/// A character driven by a local player, by their slot on the join screen.
 #[derive(Component)]
-pub struct Player;
+pub struct Player(pub usize);
 #[derive(Component)]
 pub struct Enemy;
 
@@ -33,9 +44,14 @@ pub enum Activity {
     Flinch,
     Land(Direction),
     Jump,
-    Jab,
-    Stomp,
-    Counter,
+    Attack(MoveId),
+    Shield,
+    // the shield gave out, leaving the character wide open
+    ShieldBreak,
+    Dodge,
+    // throwing the character it grabbed with a move
+    Holding(MoveId),
+    Held,
 }
 impl Default for Activity {
     fn default() -> Self {
@@ -45,14 +61,91 @@ impl Default for Activity {
 
 #[derive(Component, Default)]
 pub struct ActivityTimer(pub f32);
+/// Steps since the character's activity last changed.
 #[derive(Component, Default)]
-pub struct Hype(i32, f32);
+pub struct ActivityFrame(pub u32);
 #[derive(Component, Default)]
-pub struct Combo(i32, f32);
+pub struct Hype(pub i32, pub f32);
 #[derive(Component, Default)]
-pub struct Velocity(pub f32, pub f32);
+pub struct Combo(pub i32, pub f32);
 #[derive(Component, Default)]
 pub struct JumpCounter(pub u8);
+/// Air dodges left before the character touches a wall again.
+#[derive(Component, Default)]
+pub struct DodgeCounter(pub u8);
+/// What's left of the character's shield. It wears down while it's up and
+/// when it blocks hits, breaks when it runs out, and slowly recovers.
+#[derive(Component)]
+pub struct Shield(pub f32);
+
+pub const SHIELD_MAX: f32 = 100.;
+
+impl Default for Shield {
+    fn default() -> Self {
+        Shield(SHIELD_MAX)
+    }
+}
+
+/// Action presses that came while the character was busy, each with the steps
+/// it will wait before being forgotten, oldest first.
+#[derive(Component, Default)]
+pub struct InputBuffer(pub Vec<(ActionType, u32)>);
+
+impl InputBuffer {
+    /// keeps `action` for `steps`, starting over if it was already waiting
+    pub fn push(&mut self, action: ActionType, steps: u32) {
+        self.0.retain(|(buffered, _)| *buffered != action);
+        self.0.push((action, steps));
+    }
+}
+
+/// How many steps a press that couldn't be acted on is kept for.
+pub struct InputBufferSettings {
+    pub steps: u32,
+}
+
+impl Default for InputBufferSettings {
+    fn default() -> Self {
+        InputBufferSettings { steps: 8 }
+    }
+}
+
+/// Put on a grabbed character, pointing at whoever is holding it.
+#[derive(Component)]
+pub struct HeldBy {
+    pub holder: Entity,
+    /// the grab, for the throw that follows
+    pub move_id: MoveId,
+}
+/// Damage taken so far, in percent. The more a character has, the further it flies.
+#[derive(Component, Default)]
+pub struct Damage(pub f32);
+/// Heavier characters are launched less far by the same hit.
+#[derive(Component)]
+pub struct Weight(pub f32);
+
+pub const DEFAULT_WEIGHT: f32 = 100.;
+
+/// How a launched character can steer out of the hit that sent it flying,
+/// taken from the move that hit it.
+#[derive(Component)]
+pub struct Launched {
+    /// the most, in radians, the character's stick turns the launch when its hit-stop ends
+    pub influence_angle: f32,
+    /// the share of its speed the character sheds once its hitstun is over
+    pub momentum_cancel: f32,
+}
+
+/// Steps left of the freeze both sides of a hit are held in. Frozen characters
+/// don't move, steer or advance their activity.
+#[derive(Component)]
+pub struct HitStop(pub u32);
+
+impl Default for Weight {
+    fn default() -> Self {
+        Weight(DEFAULT_WEIGHT)
+    }
+}
 
 #[derive(PartialEq)]
 enum FacingDirection {
@@ -69,6 +162,12 @@ impl Default for FacingDirection {
 #[derive(Component, Default)]
 pub struct Facing(FacingDirection);
 
+impl Facing {
+    pub fn is_right(&self) -> bool {
+        self.0 == FacingDirection::Right
+    }
+}
+
 // semantic nitpicking: this could be called "acceleration", but since it is
 // declared by the divine heavens of the input manager, Input Influence seems
 // slightly more appropriate 
@@ -79,12 +178,20 @@ pub struct InputInfluence(pub f32, pub f32);
 pub struct CharacterBundle {
 	activity: Activity,
     timer: ActivityTimer,
+    frame: ActivityFrame,
 	hype: Hype,
 	combo: Combo,
     velocity: Velocity,
     influence: InputInfluence,
+    buffer: InputBuffer,
     jumps: JumpCounter,
+    dodges: DodgeCounter,
+    shield: Shield,
     facing: Facing,
+    body: DynamicBody,
+    hurtbox: Hurtbox,
+    damage: Damage,
+    weight: Weight,
 }
 
 pub type CharacterFilter = (With<Activity>, With<ActivityTimer>, With<Hype>, With<Combo>, With<Velocity>);
@@ -92,64 +199,120 @@ pub type CharacterFilter = (With<Activity>, With<ActivityTimer>, With<Hype>, Wit
 pub const TIME_STEP: f32 = 1.0 / 60.0;
 pub const PLAYER_RADIUS: f32 = 25.;
 
+pub const MAX_PLAYERS: usize = 4;
+const PLAYER_SPACING: f32 = PLAYER_RADIUS * 4.;
+
+/// How many local players the match is for.
+pub struct PlayerCount(pub usize);
+
+impl Default for PlayerCount {
+    fn default() -> Self {
+        PlayerCount(1)
+    }
+}
+
+/// Players that have left the stage, in the order they went out.
+#[derive(Default)]
+pub struct Eliminations(pub Vec<usize>);
+
 pub const JUMP_SPEED: f32 = 200.;
 pub const GRAVITY_Y: f32 = -200.;
 const GRAVITY_Y_PER_STEP: f32 = GRAVITY_Y * TIME_STEP;
 
-pub fn setup(mut commands: Commands) {
-    let shape = shapes::Circle {
-        radius: PLAYER_RADIUS,
-        ..Default::default()
-    };
+// a press keeps the shield up for this long, and pressing again keeps it up longer
+const SHIELD_TIME: f32 = 0.5;
+// shield lost per second while it's up
+const SHIELD_DRAIN: f32 = 20.;
+// shield regained per second while it's down
+const SHIELD_REGEN: f32 = 12.;
+// shield lost for every percent of damage a blocked hit would have dealt
+const SHIELD_HIT_SCALE: f32 = 2.;
+// the share of a blocked hit's base knockback the shield still takes
+const SHIELD_PUSHBACK: f32 = 0.3;
+const SHIELD_BREAK_TIME: f32 = 2.5;
+
+const DODGE_SPEED: f32 = 300.;
+const DODGE_TIME: f32 = 0.5;
+// the frames of a dodge that attacks pass straight through
+const DODGE_INTANGIBLE: Range<u32> = 3..21;
+
+// how far in front of its holder a grabbed character is kept
+const HOLD_DISTANCE: f32 = PLAYER_RADIUS * 2.;
+// a grabbed character is let go after this long, whatever happens to its holder
+const HELD_TIMEOUT: f32 = 5.;
+const THROW_INFLUENCE_ANGLE: f32 = 12.;
+const THROW_MOMENTUM_CANCEL: f32 = 0.3;
+
+/// Whether the character is free to jump, given what it's doing.
+pub fn can_jump(activity: &Activity, jumps: &JumpCounter) -> bool {
+    jumps.0 > 0 && match activity {
+        Activity::Jump
+        | Activity::Flinch
+        | Activity::Land(_)
+        | Activity::Dodge
+        | Activity::ShieldBreak
+        | Activity::Holding(_)
+        | Activity::Held => false,
+        _ => true,
+    }
+}
 
-    commands.spawn()
-        .insert(Player)
-        .insert_bundle(CharacterBundle::default())
-        .insert_bundle(GeometryBuilder::build_as(
-            &shape,
-            DrawMode::Outlined {
-                fill_mode: FillMode::color(Color::CYAN),
-                outline_mode: StrokeMode::new(Color::BLACK, 4.0),
-            },
-            Transform::default(),
-        ));
+// one of the level's enemy spawn points, or anywhere in the arena if it has none
+fn random_spawn_location(rng: &mut MatchRng, level: &LevelDefinition) -> Transform {
+    if !level.enemy_spawns.is_empty() {
+        let spawn = level.enemy_spawns[rng.0.gen_range(0..level.enemy_spawns.len())];
+        return Transform::from_xyz(spawn.x, spawn.y, 0.)
+    }
+    let arena = level.arena;
+    Transform::from_xyz(
+        rng.0.gen_range(arena.min.x..arena.max.x),
+        rng.0.gen_range(arena.min.y..arena.max.y),
+        0.,
+    )
+}
 
-    let mut transform2 = Transform::default();
-    transform2.translation.x -= 100.;
-    
-    commands.spawn()
-        .insert(Enemy)
-        .insert(ai::Behavior::Chasing)
-        .insert_bundle(CharacterBundle::default())
-        .insert_bundle(GeometryBuilder::build_as(
-            &shape,
-            DrawMode::Outlined {
-                fill_mode: FillMode::color(Color::ORANGE_RED),
-                outline_mode: StrokeMode::new(Color::BLACK, 4.0),
-            },
-            transform2,
-        ));
+// enemies are a little lighter than the player
+const ENEMY_WEIGHT: f32 = 85.;
 
-    let mut transform3 = Transform::default();
-    transform3.translation.x += 100.; 
+fn spawn_enemy(commands: &mut Commands, transform: Transform) {
     commands.spawn()
         .insert(Enemy)
         .insert(ai::Behavior::Chasing)
+        .insert(ai::Reaction::default())
+        .insert(ai::Role::Pressure)
         .insert_bundle(CharacterBundle::default())
-        .insert_bundle(GeometryBuilder::build_as(
-            &shape,
-            DrawMode::Outlined {
-                fill_mode: FillMode::color(Color::ORANGE_RED),
-                outline_mode: StrokeMode::new(Color::BLACK, 4.0),
-            },
-            transform3,
-        ));
+        .insert(Weight(ENEMY_WEIGHT))
+        .insert(Collider::Ball(PLAYER_RADIUS))
+        .insert(transform);
+}
+
+pub fn spawn_characters(commands: &mut Commands, rng: &mut MatchRng, level: &LevelDefinition, players: usize) {
+    // players line up side by side around the level's player spawn
+    for index in 0..players {
+        let offset = (index as f32 - (players - 1) as f32 / 2.) * PLAYER_SPACING;
+        commands.spawn()
+            .insert(Player(index))
+            .insert_bundle(CharacterBundle::default())
+            .insert(Collider::Ball(PLAYER_RADIUS))
+            .insert(Transform::from_xyz(level.player_spawn.x + offset, level.player_spawn.y, 0.));
+    }
+
+    for _ in 0..2 {
+        let transform = random_spawn_location(rng, level);
+        spawn_enemy(commands, transform);
+    }
+}
+
+pub fn setup(mut spawn_timer: ResMut<EnemySpawnTimer>, mut eliminations: ResMut<Eliminations>) {
+    *spawn_timer = EnemySpawnTimer::default();
+    eliminations.0.clear();
 }
 
 pub fn handle_activity_timer(
-    mut query: Query<(&mut Activity, &mut ActivityTimer)>,
+    mut query: Query<(&mut Activity, &mut ActivityTimer, &mut ActivityFrame), Without<HitStop>>,
 ) {
-    for (mut activity, mut activity_timer) in query.iter_mut() {
+    for (mut activity, mut activity_timer, mut frame) in query.iter_mut() {
+        frame.0 += 1;
         if activity_timer.0 > 0. {
             activity_timer.0 -= TIME_STEP;
         } else {
@@ -158,128 +321,117 @@ pub fn handle_activity_timer(
     }
 }
 
-fn update_path<T: Geometry>(path: &mut Path, shape: &T) {
-    let new_path = GeometryBuilder::build_as(
-        shape,
-        DrawMode::Fill(FillMode::color(Color::GRAY)),
-        Transform::default(),
-    ).path;
-    *path = new_path;
-}
-
 pub fn handle_activity_change(
+    mut commands: Commands,
     mut query: Query<
-        (Entity, &mut Path, &Activity, &mut ActivityTimer, &mut JumpCounter, &mut Transform, &mut Velocity, &Facing),
+        (
+            Entity,
+            &Activity,
+            &mut ActivityTimer,
+            &mut ActivityFrame,
+            &mut JumpCounter,
+            &mut DodgeCounter,
+            &mut Transform,
+            &mut Velocity,
+            &Facing,
+            &InputInfluence,
+            Option<&Launched>,
+        ),
         Changed<Activity>,
     >,
+    move_set: Res<ActiveMoveSet>,
     mut audio_writer: EventWriter<audio::Event>,
 ) {
-    // each query result is a shape that has just changed activity
-    // update its shape to match the activity
+    // each query result is a character that has just changed activity
+    // start its timers and apply any immediate effects of the activity
     for (
         character,
-        mut path,
         activity,
         mut timer,
+        mut frame,
         mut jumps,
+        mut dodges,
         mut transform,
         mut velocity,
         facing,
+        influence,
+        launched,
     ) in query.iter_mut() {
+        frame.0 = 0;
+        // coming out of hitstun, the character gets a chance to check its momentum
+        if let Some(launched) = launched.filter(|_| *activity != Activity::Flinch) {
+            let slowed = cancel_momentum(
+                Vec2::new(velocity.0, velocity.1),
+                Vec2::new(influence.0, influence.1),
+                launched.momentum_cancel,
+            );
+            velocity.0 = slowed.x;
+            velocity.1 = slowed.y;
+            commands.entity(character).remove::<Launched>();
+        }
         match activity {
-            Activity::Idle => {
-                let shape = shapes::Circle {
-                    radius: PLAYER_RADIUS,
-                    ..Default::default()
-                };
-                update_path(&mut path, &shape);
-            }
+            Activity::Idle => {}
             Activity::Flinch => {
-                let shape = shapes::Circle {
-                    radius: PLAYER_RADIUS - 2.,
-                    ..Default::default()
-                };
-                update_path(&mut path, &shape);
-                timer.0 = 1.;
+                timer.0 = hitstun(Vec2::new(velocity.0, velocity.1).length());
             }
-            Activity::Land(direction) => {
-                let shape = if direction == &Direction::Top || direction == &Direction::Bottom {
-                    shapes::Ellipse {
-                        radii: Vec2::new(PLAYER_RADIUS, PLAYER_RADIUS - 5.),
-                        ..Default::default()
-                    }
-                } else {
-                    shapes::Ellipse {
-                        radii: Vec2::new(PLAYER_RADIUS - 5., PLAYER_RADIUS),
-                        ..Default::default()
-                    }
-                };
-                update_path(&mut path, &shape);
+            Activity::Land(_) => {
                 timer.0 = 0.7;
             }
             Activity::Jump => {
-                let shape = shapes::Ellipse {
-                    radii: Vec2::new(PLAYER_RADIUS - 5., PLAYER_RADIUS),
-                    ..Default::default()
-                };
-                update_path(&mut path, &shape);
                 velocity.1 = JUMP_SPEED;
                 jumps.0 -= 1;
                 timer.0 = 0.5;
                 audio_writer.send(audio::Event(character, audio::Trigger::CharacterJump, audio::Offset(0.)));
             }
-            Activity::Jab => {
-                let shape = shapes::RegularPolygon {
-                    sides: 3,
-                    feature: shapes::RegularPolygonFeature::Apothem(PLAYER_RADIUS - 5.),
-                    ..Default::default()
+            Activity::Attack(id) => {
+                let attack = match move_set.get(*id) {
+                    Some(attack) => attack,
+                    None => continue,
                 };
-                update_path(&mut path, &shape);
+                // turn the pose to point the way the character is facing
                 let sign = if facing.0 == FacingDirection::Left { 1. } else { -1. };
                 transform.rotation = Quat::from_axis_angle(
                     Vec3::new(0., 0., 1.),
                     sign * PI / 2.
                 );
-                timer.0 = 0.9;
-                audio_writer.send(audio::Event(character, audio::Trigger::CharacterAttack, audio::Offset(1.)));
+                timer.0 = attack.duration();
+                hitbox::spawn_move_hitbox(
+                    &mut commands,
+                    character,
+                    *id,
+                    attack,
+                    transform.translation,
+                    facing.0 == FacingDirection::Right,
+                );
+                audio_writer.send(audio::Event(character, audio::Trigger::CharacterAttack, audio::Offset(attack.audio_offset)));
             }
-            Activity::Stomp => {
-                let shape = shapes::RegularPolygon {
-                    sides: 4,
-                    feature: shapes::RegularPolygonFeature::Apothem(PLAYER_RADIUS),
-                    ..Default::default()
-                };
-                update_path(&mut path, &shape);
-                timer.0 = 2.;
-                audio_writer.send(audio::Event(character, audio::Trigger::CharacterAttack, audio::Offset(-2.)));
+            Activity::Shield => {
+                timer.0 = SHIELD_TIME;
             }
-            Activity::Counter => {
-                let shape = shapes::RegularPolygon {
-                    sides: 8,
-                    feature: shapes::RegularPolygonFeature::Apothem(PLAYER_RADIUS),
-                    ..Default::default()
-                };
-                update_path(&mut path, &shape);
-                timer.0 = 2.;
-                audio_writer.send(audio::Event(character, audio::Trigger::CharacterAttack, audio::Offset(3.)));
+            Activity::ShieldBreak => {
+                timer.0 = SHIELD_BREAK_TIME;
+                audio_writer.send(audio::Event(character, audio::Trigger::ShieldBreak, audio::Offset(0.)));
+            }
+            Activity::Dodge => {
+                // the dodge goes wherever the stick points, or nowhere at all
+                let direction = Vec2::new(influence.0, influence.1).normalize_or_zero();
+                velocity.0 = direction.x * DODGE_SPEED;
+                velocity.1 = direction.y * DODGE_SPEED;
+                dodges.0 -= 1;
+                timer.0 = DODGE_TIME;
+            }
+            Activity::Holding(id) => {
+                timer.0 = move_set.get(*id)
+                    .and_then(|attack| attack.throw.as_ref())
+                    .map_or(0., |throw| throw.hold_duration());
+            }
+            Activity::Held => {
+                timer.0 = HELD_TIMEOUT;
             }
         }
     }
 }
 
-// square + circle
-fn check_for_player_and_brick_collision(
-    player_location: &Vec3,
-    brick_location: &Vec3,
-) -> Option<Direction> {
-    collision::cmp_circle_and_square(
-        player_location,
-        PLAYER_RADIUS,
-        brick_location,
-        BRICK_APOTHEM,
-    )
-}
-
 pub fn handle_turning(
     mut query: Query<(&mut Facing, &Activity, &InputInfluence), Changed<Velocity>>, 
 ) {
@@ -295,25 +447,13 @@ pub fn handle_turning(
     }
 }
 
-pub fn handle_physics(
-    mut characters_query: Query<
-        (&mut Velocity, &mut Activity, &mut JumpCounter, &mut Transform, &InputInfluence),
-        Or<(With<Player>, With<Enemy>)>,
-    >,
-    mut bricks_query: Query<
-        (&mut Durability, &Transform),
-        Or<(With<Durability>, Without<Player>, Without<Enemy>)>,
-    >,
+pub fn handle_movement(
+    mut query: Query<(&mut Velocity, &Activity, &InputInfluence), Without<HitStop>>,
+    move_set: Res<ActiveMoveSet>,
 ) {
-    for (
-        mut velocity,
-        mut activity,
-        mut jumps,
-        mut transform,
-        influence,
-    ) in characters_query.iter_mut() {
+    for (mut velocity, activity, influence) in query.iter_mut() {
         // adjust the influence of inputs based on the character's state
-        let adjusted_influence = match activity.as_ref() {
+        let adjusted_influence = match activity {
             Activity::Idle => (influence.0, influence.1),
             Activity::Flinch => (0., 0.),
             Activity::Land(direction) => {
@@ -324,238 +464,426 @@ pub fn handle_physics(
                 }
             }
             Activity::Jump => (influence.0, influence.1),
-            Activity::Jab => (influence.0 / 2., influence.1 / 2.),
-            Activity::Stomp => (influence.0 / 2., influence.1 / 2.),
-            Activity::Counter => (influence.0 / 2., influence.1 / 2.),
+            Activity::Attack(id) => {
+                let mobility = move_set.get(*id).map_or(0.5, |attack| attack.mobility);
+                (influence.0 * mobility, influence.1 * mobility)
+            }
+            Activity::Shield
+            | Activity::ShieldBreak
+            | Activity::Dodge
+            | Activity::Holding(_)
+            | Activity::Held => (0., 0.),
         };
-        // predict next location
         let vec2_velocity = Vec2::new(velocity.0, velocity.1);
         let damping = 0.000003 * vec2_velocity.length_squared();
-        let mut adjusted_velocity = (
-            velocity.0 + 3. * adjusted_influence.0 - damping * (if vec2_velocity.x.is_sign_positive() { -1. } else { 1. }),
-            velocity.1 + adjusted_influence.1 + GRAVITY_Y_PER_STEP - damping * (if vec2_velocity.y.is_sign_positive() { -1. } else { 1. }),
-        );
-        let next_location = Vec3::new(
-            transform.translation.x + adjusted_velocity.0 * TIME_STEP,
-            transform.translation.y + adjusted_velocity.1 * TIME_STEP,
-            0.
-        );
+        velocity.0 += 3. * adjusted_influence.0 - damping * (if vec2_velocity.x.is_sign_positive() { -1. } else { 1. });
+        velocity.1 += adjusted_influence.1 + GRAVITY_Y_PER_STEP - damping * (if vec2_velocity.y.is_sign_positive() { -1. } else { 1. });
+    }
+}
 
-        // check for collisions with walls at speed
-        for (mut durability, transform) in bricks_query.iter_mut() {
-            if let Some(collision) = check_for_player_and_brick_collision(
-                &next_location,
-                &transform.translation,
-            ) {
-                let impact = if collision == Direction::Right || collision == Direction::Left {
-                    adjusted_velocity.0
-                } else {
-                    adjusted_velocity.1
-                };
-                if (
-                    collision == Direction::Right && adjusted_velocity.0.is_sign_positive()
-                ) || (
-                    collision == Direction::Left && adjusted_velocity.0.is_sign_negative()
-                ) {
-                    adjusted_velocity.0 = 0.;
-                } else if (
-                    collision == Direction::Top && adjusted_velocity.1.is_sign_positive()
-                ) || (
-                    collision == Direction::Bottom && adjusted_velocity.1.is_sign_negative()
-                ) {
-                    adjusted_velocity.1 = 0.;
-                }
+// the side of the character that was hit, given a normal pointing towards it
+fn direction_from_normal(normal: Vec2) -> Direction {
+    if normal.x.abs() >= normal.y.abs() {
+        if normal.x.is_sign_positive() { Direction::Left } else { Direction::Right }
+    } else {
+        if normal.y.is_sign_positive() { Direction::Bottom } else { Direction::Top }
+    }
+}
 
-                jumps.0 = 2;
-                if impact > 100. {
-                    *activity = Activity::Land(collision);
-                    durability.0 -= impact;
+const LANDING_IMPULSE: f32 = 100.;
+
+pub fn handle_contacts(
+    mut events: EventReader<ContactEvent>,
+    mut characters_query: Query<(&mut Activity, &mut JumpCounter, &mut DodgeCounter)>,
+    mut bricks_query: Query<&mut Durability>,
+) {
+    for contact in events.iter() {
+        if let Ok((mut activity, mut jumps, mut dodges)) = characters_query.get_mut(contact.body) {
+            // any wall contact gives the jumps and the air dodge back
+            jumps.0 = 2;
+            dodges.0 = 1;
+            if contact.impulse > LANDING_IMPULSE {
+                *activity = Activity::Land(direction_from_normal(contact.normal));
+                if let Ok(mut durability) = bricks_query.get_mut(contact.other) {
+                    durability.0 -= contact.impulse;
                 }
             }
         }
+    }
+}
 
-        transform.translation.x += adjusted_velocity.0 * TIME_STEP;
-        transform.translation.y += adjusted_velocity.1 * TIME_STEP;
-        velocity.0 = adjusted_velocity.0;
-        velocity.1 = adjusted_velocity.1;
+/// Whether the character is in the active frames of a counter.
+pub fn is_countering(move_set: &ActiveMoveSet, activity: &Activity, frame: &ActivityFrame) -> bool {
+    match activity {
+        Activity::Attack(id) => move_set.get(*id)
+            .map_or(false, |attack| attack.counter && attack.is_active(frame.0)),
+        _ => false,
     }
 }
 
-#[derive(Default)]
-struct AttackResult {
-    launch: Vec2
-}
-
-impl AttackResult {
-    fn new(direction: Direction) -> Self {
-        match direction {
-            Direction::Top => Self {
-                launch: Vec2::new(0., 1.),
-            },
-            Direction::Right => Self {
-                launch: Vec2::new(PI / 3., PI / 6.),
-            },
-            Direction::Bottom => Self {
-                launch: Vec2::new(0., -1.),
-            },
-            Direction::Left => Self {
-                launch: Vec2::new(-PI / 3., PI / 6.),
-            },
-        }
+/// Whether attacks pass through the character, during the middle of a dodge.
+pub fn is_intangible(activity: &Activity, frame: &ActivityFrame) -> bool {
+    *activity == Activity::Dodge && DODGE_INTANGIBLE.contains(&frame.0)
+}
+
+/// The launch speed of a hit: it grows with the damage the target has taken,
+/// including the hit's own, and shrinks with the target's weight.
+pub fn knockback(base: f32, growth: f32, damage: f32, weight: f32) -> f32 {
+    (base + growth * damage) * DEFAULT_WEIGHT / weight
+}
+
+const HIT_STOP_MIN_STEPS: f32 = 3.;
+const HIT_STOP_STEPS_PER_SPEED: f32 = 1. / 40.;
+const HIT_STOP_MAX_STEPS: f32 = 20.;
+
+// how long both sides of a hit freeze for, in steps
+fn hit_stop(knockback: f32) -> u32 {
+    (HIT_STOP_MIN_STEPS + knockback * HIT_STOP_STEPS_PER_SPEED).min(HIT_STOP_MAX_STEPS).round() as u32
+}
+
+const HITSTUN_MIN: f32 = 0.5;
+const HITSTUN_PER_SPEED: f32 = 1. / 300.;
+const HITSTUN_MAX: f32 = 2.;
+
+// how long a character launched at `speed` flinches for, in seconds
+fn hitstun(speed: f32) -> f32 {
+    (HITSTUN_MIN + speed * HITSTUN_PER_SPEED).min(HITSTUN_MAX)
+}
+
+// turns a launch towards the stick, by up to `max_angle`; only the part of the
+// stick across the launch counts
+fn influence_launch(velocity: Vec2, stick: Vec2, max_angle: f32) -> Vec2 {
+    let speed = velocity.length();
+    if speed == 0. {
+        return velocity
     }
+    let across = (velocity / speed).perp_dot(stick.clamp_length_max(1.));
+    Mat2::from_angle(max_angle * across) * velocity
 }
 
-fn calculate_attack_collision(
-    activity: &Activity,
-    attacker: &Transform,
-    facing: &Facing,
-    target: &Transform,
-) -> Option<AttackResult> {
-    match activity {
-        Activity::Jab => {
-            // attacker: triangle; target: circle
-            let triangle_center = Vec2::new(attacker.translation.x, attacker.translation.y);
-            let tip_vertex = triangle_center + Vec2::new(
-                if facing.0 == FacingDirection::Right { PLAYER_RADIUS - 5. } else { PLAYER_RADIUS - 5. },
-                0.,
+// slows a character coming out of hitstun, more so if it is holding against its flight
+fn cancel_momentum(velocity: Vec2, stick: Vec2, momentum_cancel: f32) -> Vec2 {
+    let speed = velocity.length();
+    if speed == 0. {
+        return velocity
+    }
+    let against = -(velocity / speed).dot(stick.clamp_length_max(1.)).min(0.);
+    velocity * (1. - (momentum_cancel * (1. + against)).min(1.))
+}
+
+pub fn handle_hit_stop(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut HitStop, &mut Velocity, &InputInfluence, Option<&Launched>)>,
+) {
+    for (character, mut hit_stop, mut velocity, influence, launched) in query.iter_mut() {
+        if hit_stop.0 > 0 {
+            hit_stop.0 -= 1;
+            continue
+        }
+        commands.entity(character)
+            .remove::<HitStop>()
+            .remove::<Frozen>();
+        // the stick the victim is holding as it's let go decides where it flies
+        if let Some(launched) = launched {
+            let influenced = influence_launch(
+                Vec2::new(velocity.0, velocity.1),
+                Vec2::new(influence.0, influence.1),
+                launched.influence_angle,
             );
-            let vertical_vertices = (
-                triangle_center + Vec2::new(0., - PLAYER_RADIUS / 2.),
-                triangle_center + Vec2::new(0., PLAYER_RADIUS / 2.),
+            velocity.0 = influenced.x;
+            velocity.1 = influenced.y;
+        }
+    }
+}
+
+// what became of an attack that reached its target
+enum HitOutcome {
+    // with the speed the target was launched at
+    Landed(f32),
+    Countered,
+    Blocked,
+    // by the grab with this move
+    Grabbed(MoveId),
+    // the target was already reeling from another hit, or in someone's grip
+    Ignored,
+}
+
+pub fn handle_attack_collision(
+    mut commands: Commands,
+    mut hitbox_query: Query<(Entity, &mut Hitbox, &Transform)>,
+    mut character_query: Query<
+        (Entity, &mut Activity, &ActivityFrame, &mut Hype, &mut Combo, &mut Damage, &Weight, &mut Velocity, &Transform, &Hurtbox, &mut Shield),
+        Without<Hitbox>,
+    >,
+    mut projectile_query: Query<&mut Velocity, (With<Projectile>, With<Hitbox>)>,
+    move_set: Res<ActiveMoveSet>,
+    mut writer: EventWriter<audio::Event>,
+) {
+    // hitboxes that meet cancel each other out, unless one outranks the other
+    let mut clashed = Vec::new();
+    for [(entity1, hitbox1, transform1), (entity2, hitbox2, transform2)] in hitbox_query.iter_combinations() {
+        // grabs reach past attacks rather than trading with them
+        if hitbox1.owner == hitbox2.owner
+            || !hitbox1.is_active()
+            || !hitbox2.is_active()
+            || hitbox1.grab.is_some()
+            || hitbox2.grab.is_some() {
+            continue
+        }
+        if geometry::overlaps(
+            (&hitbox1.collider, transform1.translation.truncate()),
+            (&hitbox2.collider, transform2.translation.truncate()),
+        ) {
+            if hitbox1.priority <= hitbox2.priority {
+                clashed.push(entity1);
+            }
+            if hitbox2.priority <= hitbox1.priority {
+                clashed.push(entity2);
+            }
+        }
+    }
+    for hitbox in clashed.iter() {
+        commands.entity(*hitbox).despawn_recursive();
+    }
+
+    // each character only takes the highest priority hitbox touching it
+    let mut hits: Vec<(Entity, Entity, i32)> = Vec::new();
+    for (entity, hitbox, transform) in hitbox_query.iter() {
+        if !hitbox.is_active() || clashed.contains(&entity) {
+            continue
+        }
+        for (target, activity, frame, _, _, _, _, _, target_transform, hurtbox, _) in character_query.iter() {
+            if target == hitbox.owner || hitbox.hit.contains(&target) || is_intangible(activity, frame) {
+                continue
+            }
+            let is_touching = geometry::overlaps(
+                (&hitbox.collider, transform.translation.truncate()),
+                (&hurtbox.0, target_transform.translation.truncate()),
             );
-            let maybe_collision = collision::cmp_circle_and_triangle(
-                &target.translation,
-                PLAYER_RADIUS,
-                if facing.0 == FacingDirection::Right {
-                    (vertical_vertices.0, tip_vertex, vertical_vertices.1)
+            if !is_touching {
+                continue
+            }
+            match hits.iter_mut().find(|(hit_target, _, _)| *hit_target == target) {
+                Some(hit) if hit.2 < hitbox.priority => *hit = (target, entity, hitbox.priority),
+                Some(_) => {}
+                None => hits.push((target, entity, hitbox.priority)),
+            }
+        }
+    }
+
+    for (target, entity, _) in hits {
+        let (owner, grab, launch, hit_damage, base_knockback, knockback_growth, launched) = match hitbox_query.get_mut(entity) {
+            Ok((_, mut hitbox, _)) => {
+                hitbox.hit.push(target);
+                let launched = Launched {
+                    influence_angle: hitbox.influence_angle,
+                    momentum_cancel: hitbox.momentum_cancel,
+                };
+                (hitbox.owner, hitbox.grab, hitbox.launch, hitbox.damage, hitbox.base_knockback, hitbox.knockback_growth, launched)
+            }
+            Err(_) => continue,
+        };
+        let (owner_hype, owner_is_grabbing) = character_query.get(owner).map_or(
+            (0, false),
+            |(_, activity, _, hype, ..)| (hype.0, grab.map_or(false, |id| *activity == Activity::Attack(id))),
+        );
+        // a grab only holds one character, and not if its owner was caught first
+        if grab.is_some() && !owner_is_grabbing {
+            continue
+        }
+
+        // first the target takes the hit...
+        let (outcome, target_combo) = match character_query.get_mut(target) {
+            Ok((_, mut activity, frame, mut hype, mut combo, mut damage, weight, mut velocity, _, _, mut shield)) => {
+                if *activity == Activity::Flinch || *activity == Activity::Held {
+                    (HitOutcome::Ignored, combo.0)
+                } else if let Some(id) = grab {
+                    velocity.0 = 0.;
+                    velocity.1 = 0.;
+                    *activity = Activity::Held;
+                    commands.entity(target)
+                        .insert(HeldBy { holder: owner, move_id: id })
+                        .insert(Frozen);
+                    (HitOutcome::Grabbed(id), combo.0)
+                } else if is_countering(&move_set, activity.as_ref(), frame) {
+                    hype.0 += 1;
+                    hype.1 = 4.;
+                    (HitOutcome::Countered, combo.0)
+                } else if *activity == Activity::Shield {
+                    // the shield soaks up the hit, and gets pushed back a little
+                    shield.0 -= hit_damage * SHIELD_HIT_SCALE;
+                    if shield.0 <= 0. {
+                        shield.0 = 0.;
+                        *activity = Activity::ShieldBreak;
+                    }
+                    let pushback = launch * base_knockback * SHIELD_PUSHBACK;
+                    velocity.0 = pushback.x;
+                    velocity.1 = pushback.y;
+                    (HitOutcome::Blocked, combo.0)
                 } else {
-                    (vertical_vertices.0, vertical_vertices.1, tip_vertex)
-                },
-            );
-            if (attacker.translation - target.translation).length() < PLAYER_RADIUS {
-                let direction = if facing.0 == FacingDirection::Right { Direction::Right } else { Direction::Left };
-                Some(AttackResult::new(direction))
-            } else {
-                None
+                    damage.0 += hit_damage;
+                    let speed = knockback(base_knockback, knockback_growth, damage.0, weight.0);
+                    let new_velocity = launch * speed;
+                    velocity.0 = new_velocity.x;
+                    velocity.1 = new_velocity.y;
+                    combo.0 += 1;
+                    combo.1 = 6.;
+                    *activity = Activity::Flinch;
+                    commands.entity(target).insert(launched);
+                    (HitOutcome::Landed(speed), combo.0)
+                }
             }
-        },
-        Activity::Stomp => {
-            // attacker: square; target: circle
-            let maybe_collision = collision::cmp_circle_and_square(
-                &target.translation,
-                PLAYER_RADIUS,
-                &attacker.translation,
-                PLAYER_RADIUS,
-            );
-            if let Some(direction) = maybe_collision {
-                Some(AttackResult::new(direction))
-            } else {
-                None
+            Err(_) => continue,
+        };
+        // ...then the attacker is rewarded for it, or punished for being countered
+        let mut attacker_hype = owner_hype;
+        if let Ok((_, mut activity, _, mut hype, mut combo, _, _, _, _, _, _)) = character_query.get_mut(owner) {
+            match outcome {
+                HitOutcome::Landed(_) => {
+                    hype.0 += 1;
+                    hype.1 = 4.;
+                }
+                HitOutcome::Countered => {
+                    combo.0 += 1;
+                    combo.1 = 6.;
+                }
+                HitOutcome::Grabbed(id) => {
+                    *activity = Activity::Holding(id);
+                }
+                HitOutcome::Blocked | HitOutcome::Ignored => {}
             }
-        },
-        Activity::Counter => {
-            // attacker: circle; target: circle
-            if collision::cmp_circles(
-                attacker.translation,
-                target.translation,
-                PLAYER_RADIUS,
-            ) {
-                Some(AttackResult::default())
-            } else {
-                None
+            attacker_hype = hype.0;
+        }
+        // a countered projectile turns around, and now belongs to the counter
+        let is_projectile = projectile_query.get(entity).is_ok();
+        if let (HitOutcome::Countered, Ok(mut velocity)) = (&outcome, projectile_query.get_mut(entity)) {
+            velocity.0 = -velocity.0;
+            velocity.1 = -velocity.1;
+            if let Ok((_, mut hitbox, _)) = hitbox_query.get_mut(entity) {
+                hitbox.owner = target;
+                hitbox.launch.x = -hitbox.launch.x;
+                hitbox.hit.clear();
             }
-        },
-        _ => None
+        }
+        // both sides freeze for a moment, longer the harder the hit, though
+        // whoever fired a projectile is too far away to feel it
+        let stop = match outcome {
+            HitOutcome::Landed(speed) => Some(hit_stop(speed)),
+            HitOutcome::Countered | HitOutcome::Blocked => Some(hit_stop(0.)),
+            HitOutcome::Grabbed(_) | HitOutcome::Ignored => None,
+        };
+        if let Some(stop) = stop {
+            let frozen = if is_projectile { vec![target] } else { vec![owner, target] };
+            for character in frozen {
+                if character_query.get(character).is_ok() {
+                    commands.entity(character).insert(HitStop(stop)).insert(Frozen);
+                }
+            }
+        }
+        writer.send(audio::Event(
+            target,
+            audio::Trigger::CharacterHit,
+            audio::Offset(-3. + attacker_hype as f32 + target_combo as f32),
+        ));
     }
 }
 
-const LAUNCH_COEF: f32 = 70.;
-
-pub fn handle_attack_collision(
-    mut query: Query<(Entity, &mut Activity, &mut Hype, &mut Combo, &mut Velocity, &mut Transform, &Facing)>,
+/// Keeps grabbed characters in their holder's grip, then throws them once the
+/// hold is over. If the holder is interrupted first, the character is let go.
+pub fn handle_holds(
+    mut commands: Commands,
+    mut held_query: Query<(
+        Entity,
+        &HeldBy,
+        &mut Activity,
+        &mut Transform,
+        &mut Velocity,
+        &mut Damage,
+        &Weight,
+        Option<&HitStop>,
+    )>,
+    holder_query: Query<(&Activity, &Transform, &Facing), Without<HeldBy>>,
+    move_set: Res<ActiveMoveSet>,
     mut writer: EventWriter<audio::Event>,
 ) {
-    let mut combinations = query.iter_combinations_mut();
-    while let Some([
-        (entity1, mut activity1, mut hype1, mut combo1, mut velocity1, mut transform1, facing1),
-        (entity2, mut activity2, mut hype2, mut combo2, mut velocity2, mut transform2, facing2),
-    ]) = combinations.fetch_next() {
-        let one_hits_two = calculate_attack_collision(
-            activity1.as_ref(),
-            transform1.as_ref(),
-            facing1,
-            transform2.as_ref(),
-        );
-        let mut has_collided = false;
-
-        if let Some(collision_result) = one_hits_two {
-            has_collided = true;
-            match activity2.as_ref() {
-                Activity::Counter => {
-                    combo1.0 += 1;
-                    combo1.1 = 6.;
-                    hype2.0 += 1;
-                    hype2.1 = 4.;
+    for (
+        character,
+        held_by,
+        mut activity,
+        mut transform,
+        mut velocity,
+        mut damage,
+        weight,
+        hit_stop,
+    ) in held_query.iter_mut() {
+        let throw = move_set.get(held_by.move_id).and_then(|attack| attack.throw.as_ref());
+        match (holder_query.get(held_by.holder), throw) {
+            (Ok((Activity::Holding(id), holder_transform, facing)), _)
+                if *id == held_by.move_id && *activity == Activity::Held => {
+                let side = if facing.0 == FacingDirection::Right { 1. } else { -1. };
+                transform.translation = holder_transform.translation + Vec3::new(side * HOLD_DISTANCE, 0., 0.);
+                velocity.0 = 0.;
+                velocity.1 = 0.;
+            }
+            // the hold ran its course
+            (Ok((Activity::Idle, _, facing)), Some(throw)) if *activity == Activity::Held => {
+                damage.0 += throw.damage;
+                let speed = knockback(throw.base_knockback, throw.knockback_growth, damage.0, weight.0);
+                let new_velocity = throw.launch_direction(facing.0 == FacingDirection::Right) * speed;
+                velocity.0 = new_velocity.x;
+                velocity.1 = new_velocity.y;
+                *activity = Activity::Flinch;
+                // the hit-stop lets go of the character once it's over
+                commands.entity(character)
+                    .remove::<HeldBy>()
+                    .insert(HitStop(hit_stop(speed)))
+                    .insert(Launched {
+                        influence_angle: THROW_INFLUENCE_ANGLE.to_radians(),
+                        momentum_cancel: THROW_MOMENTUM_CANCEL,
+                    });
+                writer.send(audio::Event(character, audio::Trigger::CharacterHit, audio::Offset(-3.)));
+            }
+            _ => {
+                commands.entity(character).remove::<HeldBy>();
+                if hit_stop.is_none() {
+                    commands.entity(character).remove::<Frozen>();
                 }
-                Activity::Flinch => {}
-                _ => {
-                    let new_velocity = collision_result.launch * LAUNCH_COEF * (1. + hype1.0 as f32 + combo2.0 as f32);
-                    velocity2.0 = new_velocity.x;
-                    velocity2.1 = new_velocity.y; 
-                    combo2.0 += 1;
-                    combo2.1 = 6.;
-                    hype1.0 += 1;
-                    hype1.1 = 4.;
-                    *activity2 = Activity::Flinch;
+                if *activity == Activity::Held {
+                    *activity = Activity::Idle;
                 }
             }
-            writer.send(audio::Event(
-                entity2,
-                audio::Trigger::CharacterHit,
-                audio::Offset(-3. + hype1.0 as f32 + combo2.0 as f32),
-            ));
         }
-        let two_hits_one = calculate_attack_collision(
-            activity2.as_ref(),
-            transform2.as_ref(),
-            facing2,
-            transform1.as_ref(),
-        );
-        if let Some(collision_result) = two_hits_one {
-            has_collided = true;
-            match activity1.as_ref() {
-                Activity::Counter => {
-                    combo2.0 += 1;
-                    combo2.1 = 1.5;
-                    hype1.0 += 1;
-                }
-                Activity::Flinch => {}
-                _ => {
-                    combo1.0 += 1;
-                    combo1.1 = 1.5;
-                    let new_velocity = collision_result.launch * LAUNCH_COEF * (1. + hype2.0 as f32) * (1. + combo1.0 as f32);
-                    velocity1.0 = new_velocity.x;
-                    velocity1.1 = new_velocity.y;
-                    hype2.0 += 1;
-                    *activity1 = Activity::Flinch;
+    }
+}
+
+/// Wears shields down while they're up, breaking them when they run out, and
+/// lets them recover while they're down.
+pub fn handle_shield(
+    mut query: Query<(&mut Activity, &mut Shield), Without<HitStop>>,
+) {
+    for (mut activity, mut shield) in query.iter_mut() {
+        match activity.as_ref() {
+            Activity::Shield => {
+                shield.0 -= SHIELD_DRAIN * TIME_STEP;
+                if shield.0 <= 0. {
+                    shield.0 = 0.;
+                    *activity = Activity::ShieldBreak;
                 }
             }
-            writer.send(audio::Event(
-                entity1,
-                audio::Trigger::CharacterHit,
-                audio::Offset(-3. + hype2.0 as f32 + combo1.0 as f32),
-            ));
+            // a broken shield only starts to recover once the character does
+            Activity::ShieldBreak => {}
+            _ => {
+                shield.0 = (shield.0 + SHIELD_REGEN * TIME_STEP).min(SHIELD_MAX);
+            }
         }
     }
 }
 
 pub fn handle_status_tick(
     mut query: Query<(&mut Hype, &mut Combo)>,
-    time: Res<Time>,
 ) {
     for (mut hype, mut combo) in query.iter_mut() {
-        let dt = time.delta().as_secs_f32();
-        hype.1 -= dt;
+        hype.1 -= TIME_STEP;
         if hype.1 <= 0. {
             if hype.0 > 0 {
                 hype.0 -= 1;
@@ -564,7 +892,7 @@ pub fn handle_status_tick(
                 hype.1 = 0.;
             }
         }
-        combo.1 -= dt;
+        combo.1 -= TIME_STEP;
         if combo.1 <= 0. {
             if combo.0 > 0 {
                 combo.0 -= 1;
@@ -576,26 +904,6 @@ pub fn handle_status_tick(
     }
 }
 
-pub fn handle_status_change(
-    mut query: Query<
-        (&mut DrawMode, &Hype, &Combo, Option<&Player>, Option<&Enemy>),
-        Or<(Changed<Hype>, Changed<Combo>)>,
-    >,
-) {
-    for (mut draw_mode, hype, combo, player, enemy) in query.iter_mut() {
-        let fill = match *draw_mode {
-            DrawMode::Outlined { fill_mode, outline_mode } => { fill_mode.color }
-            DrawMode::Fill(fill_mode) => { fill_mode.color }
-            DrawMode::Stroke(stroke_mode) => { stroke_mode.color }
-        };
-        let outline = Color::rgb(0.1 + 0.3 * (combo.0 as f32), 0.1, 0.1 + 0.3 * (hype.0 as f32));
-        *draw_mode = DrawMode::Outlined {
-            fill_mode: FillMode::color(fill),
-            outline_mode: StrokeMode::new(outline, 4.0),
-        }
-    }
-}
-
 pub struct EnemySpawnTimer(f32);
 
 impl Default for EnemySpawnTimer {
@@ -607,6 +915,8 @@ impl Default for EnemySpawnTimer {
 pub fn handle_enemy_spawn_timer(
     mut commands: Commands,
     mut spawn_timer: ResMut<EnemySpawnTimer>,
+    mut rng: ResMut<MatchRng>,
+    level: Res<ActiveLevel>,
     query: Query<&Enemy>,
 ) {
     let num_enemies = query.iter().count();
@@ -614,43 +924,32 @@ pub fn handle_enemy_spawn_timer(
 
     spawn_timer.0 -= TIME_STEP;
     if spawn_timer.0 <= 0. {
-        let shape = shapes::Circle {
-            radius: PLAYER_RADIUS,
-            ..Default::default()
-        };
-        commands.spawn()
-            .insert(Enemy)
-            .insert(ai::Behavior::Chasing)
-            .insert_bundle(CharacterBundle::default())
-            .insert_bundle(GeometryBuilder::build_as(
-                &shape,
-                DrawMode::Outlined {
-                    fill_mode: FillMode::color(Color::ORANGE_RED),
-                    outline_mode: StrokeMode::new(Color::BLACK, 4.0),
-                },
-                Transform::default(),
-            ));
+        if let Some(definition) = level.definition.as_ref() {
+            let transform = random_spawn_location(&mut rng, definition);
+            spawn_enemy(&mut commands, transform);
+        }
 
         *spawn_timer = EnemySpawnTimer::default();
     }
 }
 
-fn is_offstage(translation: Vec3) -> bool {
-    let is_offstage_right = translation.x > LEVEL_WIDTH;
-    let is_offstage_left = translation.x < -LEVEL_WIDTH;
-    let is_offstage_bottom = translation.y < -LEVEL_HEIGHT;
-    let is_offstage_top = translation.y > LEVEL_HEIGHT;
-    is_offstage_top || is_offstage_bottom || is_offstage_left || is_offstage_right
+fn is_offstage(translation: Vec3, kill_zone: &Bounds) -> bool {
+    !kill_zone.contains(translation.truncate())
 }
 
 pub fn detect_enemy_death_system(
 	mut commands: Commands,
 	mut score: ResMut<Score>,
+	level: Res<ActiveLevel>,
 	mut enemy_query: Query<(Entity, &Transform), With<Enemy>>,
 ) {
+    let kill_zone = match level.kill_zone() {
+        Some(kill_zone) => kill_zone,
+        None => return,
+    };
     let mut count = 0;
     for (enemy, transform) in enemy_query.iter_mut() {
-        if is_offstage(transform.translation) {
+        if is_offstage(transform.translation, &kill_zone) {
             count += 1;
             commands.entity(enemy).despawn()
         }
@@ -658,14 +957,32 @@ pub fn detect_enemy_death_system(
     score.0 +=  count;
 }
 
+/// Knocks out players that leave the stage; the match is over once every
+/// player is out.
 pub fn detect_gameover_system(
     mut commands: Commands,
 	mut state: ResMut<State<AppState>>,
-	player_query: Query<(Entity, &mut Transform), With<Player>>,
+	mut eliminations: ResMut<Eliminations>,
+	level: Res<ActiveLevel>,
+	player_query: Query<(Entity, &Player, &Transform)>,
 ) {
-	for (entity, player) in player_query.iter() {
-		if is_offstage(player.translation) {
-            state.set(AppState::GameOver).unwrap();
+    let kill_zone = match level.kill_zone() {
+        Some(kill_zone) => kill_zone,
+        None => return,
+    };
+    let mut remaining = 0;
+    let mut eliminated = false;
+	for (entity, player, transform) in player_query.iter() {
+		if is_offstage(transform.translation, &kill_zone) {
+            info!("player {} is out", player.0 + 1);
+            eliminations.0.push(player.0);
+            commands.entity(entity).despawn_recursive();
+            eliminated = true;
+        } else {
+            remaining += 1;
         }
 	}
+    if eliminated && remaining == 0 {
+        state.set(AppState::GameOver).unwrap();
+    }
 }
@@ -1,95 +1,623 @@
 use bevy::prelude::*;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{cmp::Ordering, str::FromStr};
 
-use crate::game::player::{
-    Activity,
-    ActivityTimer,
-    JumpCounter,
-    Hype,
-    Combo,
-    InputInfluence,
-    Enemy,
-    Player,
-    Velocity,
+use crate::game::{
+    input::ActionType,
+    level::{ActiveLevel, Bounds, BrickGrid, LevelDefinition, CELL_SIZE},
+    player::{
+        can_jump,
+        moves::{ActiveMoveSet, MoveDefinition, MoveId},
+        Activity,
+        ActivityTimer,
+        Damage,
+        DodgeCounter,
+        JumpCounter,
+        Hype,
+        Combo,
+        InputInfluence,
+        Launched,
+        Shield,
+        PLAYER_RADIUS,
+        SHIELD_MAX,
+    },
+    physics::Velocity,
+    rng::MatchRng,
 };
 
-#[derive(Component, Debug)]
+// Enemies weigh up what they could do every step and settle on whichever
+// behavior scores best, but only act on a change of mind once their reaction
+// time has passed. How quick, sloppy and bold they are depends on the
+// difficulty picked for the match. Whatever they're doing, an enemy that gets
+// sent flying drops it to get back on stage.
+//
+// The same systems can play for players too, which lets the AI be measured
+// against itself: each system is generic over the marker of the characters it
+// plays for and the marker of the ones they go after.
+
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
 pub enum Behavior {
     Chasing,
     Evading,
     Attacking,
+    /// sent flying, or out past the walls, and heading back
+    Recovering,
+}
+
+/// A behavior an enemy has started to favor but hasn't acted on yet.
+#[derive(Component, Default)]
+pub struct Reaction {
+    pub pending: Option<Behavior>,
+    /// steps until the enemy switches to it
+    pub steps: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// How an enemy plays.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AiSettings {
+    /// steps between an enemy deciding to change what it's doing and doing it
+    pub reaction_delay: u32,
+    /// chance of a wild swing, or of missing an attack it should have defended
+    pub mistake_rate: f64,
+    /// above 1, enemies go after the player more and back off less
+    pub aggression: f32,
 }
 
-pub fn handle_ai_behavior(
+impl Difficulty {
+    pub fn settings(&self) -> AiSettings {
+        match self {
+            Difficulty::Easy => AiSettings {
+                reaction_delay: 24,
+                mistake_rate: 0.35,
+                aggression: 0.6,
+            },
+            Difficulty::Normal => AiSettings {
+                reaction_delay: 12,
+                mistake_rate: 0.15,
+                aggression: 1.,
+            },
+            Difficulty::Hard => AiSettings {
+                reaction_delay: 4,
+                mistake_rate: 0.03,
+                aggression: 1.4,
+            },
+        }
+    }
+
+    /// the next difficulty up, wrapping around to the easiest
+    pub fn next(&self) -> Difficulty {
+        match self {
+            Difficulty::Easy => Difficulty::Normal,
+            Difficulty::Normal => Difficulty::Hard,
+            Difficulty::Hard => Difficulty::Easy,
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("unknown difficulty {}", name)),
+        }
+    }
+}
+
+/// The difficulty of the enemies in the match. Changing it mid-match has no
+/// effect on replays, which keep the one the match started with.
+#[derive(Clone, Copy, Debug)]
+pub struct AiDifficulty(pub Difficulty);
+
+impl Default for AiDifficulty {
+    fn default() -> Self {
+        AiDifficulty(Difficulty::Normal)
+    }
+}
+
+/// Settings enemies play with in place of the difficulty's, for tuning them.
+/// Replays only record the difficulty, so matches played with an override
+/// don't replay the same.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AiSettingsOverride(pub Option<AiSettings>);
+
+impl AiSettingsOverride {
+    /// what enemies play with under `difficulty`
+    pub fn settings(&self, difficulty: &AiDifficulty) -> AiSettings {
+        self.0.unwrap_or_else(|| difficulty.0.settings())
+    }
+}
+
+/// A player the AI plays for, going after the enemies with these settings
+/// instead of taking input.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct AiPlayer(pub AiSettings);
+
+// the player nearest to `position`, which is the one an enemy goes after
+fn nearest_player<'a, T>(
+    players: impl Iterator<Item = (&'a Transform, T)>,
+    position: Vec3,
+) -> Option<(&'a Transform, T)> {
+    players.min_by(|(a, _), (b, _)| {
+        a.translation.distance_squared(position)
+            .partial_cmp(&b.translation.distance_squared(position))
+            .unwrap_or(Ordering::Equal)
+    })
+}
+
+// close enough to the player to see its attacks coming
+const DEFEND_RANGE: f32 = 90.;
+// how far out enemies look for somewhere to land when recovering
+const RECOVERY_SEARCH: f32 = CELL_SIZE * 20.;
+// close enough to the player to be worth swinging at
+const ATTACK_RANGE: f32 = 100.;
+// how near the edge of the kill zone an enemy starts to worry about it
+const EDGE_MARGIN: f32 = CELL_SIZE * 4.;
+// damage at which an enemy is as wary as it gets
+const CAUTIOUS_DAMAGE: f32 = 150.;
+
+/// Whether `position` is out past the walls. Characters pressed up against a
+/// wall sink into it a little, so they aren't counted.
+fn is_past_walls(definition: &LevelDefinition, position: Vec2) -> bool {
+    let margin = Vec2::splat(PLAYER_RADIUS / 2.);
+    let inside = Bounds {
+        min: definition.arena.min - margin,
+        max: definition.arena.max + margin,
+    };
+    !inside.contains(position)
+}
+
+/// Whether an enemy has to give up on what it's doing to get back on stage:
+/// while it's been launched or grabbed, or once it's out past the walls.
+fn needs_recovery(level: &ActiveLevel, activity: &Activity, position: Vec2, is_launched: bool) -> bool {
+    let is_outside = level.definition.as_ref()
+        .map_or(false, |definition| is_past_walls(definition, position));
+    is_launched || *activity == Activity::Flinch || *activity == Activity::Held || is_outside
+}
+
+// how many cells ahead enemies look for holes in the walls
+const GAP_LOOKAHEAD: u32 = 3;
+
+/// Whether moving along `direction` from `position` runs into a hole in the walls.
+fn heads_into_gap(grid: &BrickGrid, position: Vec2, direction: Vec2) -> bool {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec2::ZERO {
+        return false
+    }
+    (1..=GAP_LOOKAHEAD).any(|step| {
+        grid.is_broken(BrickGrid::cell_of(position + direction * CELL_SIZE * step as f32))
+    })
+}
+
+/// Whether `activity` leaves a character open to a hit.
+fn is_vulnerable(activity: &Activity) -> bool {
+    matches!(activity, Activity::Flinch | Activity::ShieldBreak | Activity::Land(_) | Activity::Held)
+}
+
+/// How near `position` is to leaving the stage, from 0 well inside to 1 on the edge.
+fn edge_danger(level: &ActiveLevel, position: Vec2) -> f32 {
+    let kill_zone = match level.kill_zone() {
+        Some(kill_zone) => kill_zone,
+        None => return 0.,
+    };
+    let distance = (position - kill_zone.min).min(kill_zone.max - position).min_element();
+    (1. - distance / EDGE_MARGIN).clamp(0., 1.)
+}
+
+/// Everything an enemy weighs up when deciding what to do.
+struct Situation {
+    /// from the enemy to its target
+    to_target: Vec2,
+    target_is_attacking: bool,
+    target_is_vulnerable: bool,
+    /// hype and combo in the enemy's favor, from -1 to 1
+    advantage: f32,
+    /// jumps and dodges left, from 0 to 1
+    recovery: f32,
+    edge_danger: f32,
+    caution: f32,
+}
+
+impl Situation {
+    fn score(&self, behavior: Behavior, settings: &AiSettings) -> f32 {
+        let distance = self.to_target.length();
+        let closeness = (1. - distance / ATTACK_RANGE).clamp(0., 1.);
+        let threat = if self.target_is_attacking && distance < DEFEND_RANGE { 1. } else { 0. };
+        let opening = if self.target_is_vulnerable { 1. } else { 0. };
+        match behavior {
+            Behavior::Attacking => settings.aggression
+                * (closeness * (0.6 + 0.4 * opening) + 0.2 * self.advantage)
+                - 0.3 * threat,
+            Behavior::Evading => (2. - settings.aggression).max(0.2)
+                * (threat * (0.5 + 0.5 * (1. - self.recovery))
+                    + 0.3 * self.caution * closeness
+                    + 0.4 * self.edge_danger
+                    - 0.2 * self.advantage),
+            Behavior::Chasing => 0.1 + 0.8 * settings.aggression * (1. - closeness),
+            // never picked on score, only when the enemy is in trouble
+            Behavior::Recovering => f32::MIN,
+        }
+    }
+}
+
+const BEHAVIORS: [Behavior; 3] = [Behavior::Chasing, Behavior::Evading, Behavior::Attacking];
+
+/// Enemies are played with `A = Enemy, T = Player`, and AI players with
+/// `A = AiPlayer, T = Enemy`.
+pub fn handle_ai_behavior<A: Component, T: Component>(
     player_query: Query<
-        (&Transform, &Hype, &Combo),
-        (With<Player>, Without<Enemy>),
+        (&Transform, &Activity, &Hype, &Combo),
+        (With<T>, Without<A>),
     >,
     mut ai_query: Query<
-        (&mut Behavior, &Transform, &Hype, &Combo),
-        (With<Enemy>, Without<Player>,
+        (
+            &mut Behavior,
+            &mut Reaction,
+            &Activity,
+            &Transform,
+            &Hype,
+            &Combo,
+            &Damage,
+            &JumpCounter,
+            &DodgeCounter,
+            Option<&Launched>,
+            Option<&AiPlayer>,
+        ),
+        (With<A>, Without<T>,
     )>,
+    level: Res<ActiveLevel>,
+    difficulty: Res<AiDifficulty>,
+    tuning: Res<AiSettingsOverride>,
 ) {
-    if (player_query.get_single().is_err()) {
-        return
-    }
-    let (
-        player_transform,
-        player_hype,
-        player_combo,
-    ) = player_query.get_single().unwrap();
+    let enemy_settings = tuning.settings(&difficulty);
     for (
         mut behavior,
+        mut reaction,
+        activity,
         transform,
         hype,
         combo,
+        damage,
+        jumps,
+        dodges,
+        launched,
+        ai_player,
     ) in ai_query.iter_mut() {
-        let me_to_player = transform.translation - player_transform.translation;
-        let me_to_player = Vec2::new(me_to_player.x, me_to_player.y);
+        let settings = ai_player.map_or(enemy_settings, |ai_player| ai_player.0);
+        let position = transform.translation.truncate();
+        // getting back on stage comes before anything else, straight away
+        if needs_recovery(&level, activity, position, launched.is_some()) {
+            if *behavior != Behavior::Recovering {
+                *behavior = Behavior::Recovering;
+            }
+            reaction.pending = None;
+            continue
+        }
+        let players = player_query.iter()
+            .map(|(transform, activity, hype, combo)| (transform, (activity, hype, combo)));
+        let (player_transform, (player_activity, player_hype, player_combo)) = match nearest_player(players, transform.translation) {
+            Some(player) => player,
+            None => return,
+        };
+        let situation = Situation {
+            to_target: player_transform.translation.truncate() - position,
+            target_is_attacking: matches!(player_activity, Activity::Attack(_)),
+            target_is_vulnerable: is_vulnerable(player_activity),
+            advantage: ((hype.0 - player_hype.0 + player_combo.0 - combo.0) as f32 / 3.).clamp(-1., 1.),
+            recovery: (jumps.0 + dodges.0) as f32 / 3.,
+            edge_danger: edge_danger(&level, position),
+            caution: (damage.0 / CAUTIOUS_DAMAGE).min(1.),
+        };
+        let best = BEHAVIORS.iter()
+            .map(|behavior| (*behavior, situation.score(*behavior, &settings)))
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+            .map(|(behavior, _)| behavior)
+            .unwrap_or(Behavior::Chasing);
 
-        let is_in_range = me_to_player.length() < 100.;
-        let my_strength_advantage = hype.0 - player_hype.0 + player_combo.0 - combo.0;
-        *behavior = if is_in_range {
-            if my_strength_advantage.is_negative() {
-                Behavior::Evading
-            } else {
-                Behavior::Attacking
+        // a change of mind takes the enemy's reaction time to act on, except
+        // for what to do once back on stage
+        if *behavior == Behavior::Recovering {
+            *behavior = best;
+            reaction.pending = None;
+        } else if best == *behavior {
+            reaction.pending = None;
+        } else if reaction.pending == Some(best) {
+            reaction.steps = reaction.steps.saturating_sub(1);
+            if reaction.steps == 0 {
+                *behavior = best;
+                reaction.pending = None;
             }
         } else {
-            Behavior::Chasing
+            reaction.pending = Some(best);
+            reaction.steps = settings.reaction_delay;
+        }
+    }
+}
+
+/// The part an enemy plays in its group's attack on a player, so that they
+/// don't all pile onto the same spot.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    /// goes straight for the player
+    Pressure,
+    /// works round to one side of the player
+    Flank { right: bool },
+    /// waits in front of a hole in the walls, given by the middle of its cell
+    Guard(Vec2),
+}
+
+/// How strongly enemies keep their distance from each other, move together,
+/// and head for the spots their roles give them.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlockingSettings {
+    /// pushes enemies apart when they're within `separation_radius`
+    pub separation: f32,
+    /// turns enemies to move the way their neighbors are moving
+    pub alignment: f32,
+    /// draws enemies towards the middle of their neighbors
+    pub cohesion: f32,
+    /// pulls flankers and guards towards their spots
+    pub role: f32,
+    /// enemies closer than this count as neighbors
+    pub neighbor_radius: f32,
+    pub separation_radius: f32,
+    /// how far to the side of the player flankers wait
+    pub flank_offset: f32,
+    /// how far in front of a gap its guard waits
+    pub guard_distance: f32,
+}
+
+impl Default for FlockingSettings {
+    fn default() -> Self {
+        FlockingSettings {
+            separation: 1.,
+            alignment: 0.2,
+            cohesion: 0.1,
+            role: 1.,
+            neighbor_radius: PLAYER_RADIUS * 6.,
+            separation_radius: PLAYER_RADIUS * 3.,
+            flank_offset: PLAYER_RADIUS * 3.,
+            guard_distance: CELL_SIZE * 2.,
+        }
+    }
+}
+
+// the middle of the broken wall cell nearest `position`, if any bricks are gone
+fn nearest_gap(grid: &BrickGrid, position: Vec2) -> Option<Vec2> {
+    grid.broken()
+        .map(BrickGrid::cell_center)
+        .min_by(|a, b| {
+            a.distance_squared(position)
+                .partial_cmp(&b.distance_squared(position))
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+/// Splits the enemies going after each player into roles: the nearest one
+/// pressures it, the one nearest a broken wall guards the gap, and the rest
+/// flank it from alternating sides, starting opposite the pressure.
+pub fn handle_ai_roles<A: Component, T: Component>(
+    player_query: Query<(Entity, &Transform), (With<T>, Without<A>)>,
+    mut ai_query: Query<(Entity, &mut Role, &Transform), (With<A>, Without<T>)>,
+    grid: Res<BrickGrid>,
+) {
+    let enemies: Vec<(Entity, Vec2)> = ai_query.iter()
+        .map(|(enemy, _, transform)| (enemy, transform.translation.truncate()))
+        .collect();
+    for (player, player_transform) in player_query.iter() {
+        let target = player_transform.translation.truncate();
+        let mut group: Vec<(Entity, Vec2)> = enemies.iter()
+            .filter(|(_, position)| {
+                let players = player_query.iter().map(|(entity, transform)| (transform, entity));
+                nearest_player(players, position.extend(0.)).map(|(_, nearest)| nearest) == Some(player)
+            })
+            .copied()
+            .collect();
+        group.sort_by(|(_, a), (_, b)| {
+            a.distance_squared(target)
+                .partial_cmp(&b.distance_squared(target))
+                .unwrap_or(Ordering::Equal)
+        });
+        if group.is_empty() {
+            continue
+        }
+
+        let (pressure, pressure_position) = group.remove(0);
+        let mut roles = vec![(pressure, Role::Pressure)];
+        if !group.is_empty() {
+            if let Some(gap) = nearest_gap(&grid, target) {
+                let guard = group.iter()
+                    .enumerate()
+                    .min_by(|(_, (_, a)), (_, (_, b))| {
+                        a.distance_squared(gap)
+                            .partial_cmp(&b.distance_squared(gap))
+                            .unwrap_or(Ordering::Equal)
+                    })
+                    .map(|(index, _)| index);
+                if let Some(index) = guard {
+                    let (guard, _) = group.remove(index);
+                    roles.push((guard, Role::Guard(gap)));
+                }
+            }
+        }
+        let is_pressure_on_right = pressure_position.x > target.x;
+        for (index, (flanker, _)) in group.iter().enumerate() {
+            let right = (index % 2 == 0) != is_pressure_on_right;
+            roles.push((*flanker, Role::Flank { right }));
+        }
+
+        for (enemy, role) in roles {
+            if let Ok((_, mut current, _)) = ai_query.get_mut(enemy) {
+                if *current != role {
+                    *current = role;
+                }
+            }
         }
     }
 }
 
-pub fn handle_ai_flocking(
+/// Adds separation, alignment and cohesion to each enemy's steering, and sends
+/// chasing flankers and guards to their spots rather than straight at the player.
+pub fn handle_ai_flocking<A: Component, T: Component>(
+    player_query: Query<&Transform, (With<T>, Without<A>)>,
     mut ai_query: Query<
-        (&mut InputInfluence, &Transform, &Velocity, &JumpCounter),
-        (With<Enemy>, Without<Player>),
-    >
+        (
+            Entity,
+            &mut InputInfluence,
+            &Behavior,
+            &Role,
+            &Activity,
+            &Transform,
+            &Velocity,
+            Option<&Launched>,
+        ),
+        (With<A>, Without<T>),
+    >,
+    grid: Res<BrickGrid>,
+    settings: Res<FlockingSettings>,
 ) {
+    let flock: Vec<(Entity, Vec2, Vec2)> = ai_query.iter()
+        .map(|(enemy, _, _, _, _, transform, velocity, _)| {
+            (enemy, transform.translation.truncate(), Vec2::new(velocity.0, velocity.1))
+        })
+        .collect();
     for (
+        enemy,
         mut input_influence,
+        behavior,
+        role,
+        activity,
         transform,
         velocity,
-        jumps,
+        launched,
     ) in ai_query.iter_mut() {
-        
+        // steering while sent flying is left to the recovery
+        if *behavior == Behavior::Recovering
+            || *activity == Activity::Flinch
+            || *activity == Activity::Held
+            || launched.is_some() {
+            continue
+        }
+        let position = transform.translation.truncate();
+        let velocity = Vec2::new(velocity.0, velocity.1);
+
+        let mut separation = Vec2::ZERO;
+        let mut heading = Vec2::ZERO;
+        let mut center = Vec2::ZERO;
+        let mut neighbors = 0;
+        for (other, other_position, other_velocity) in flock.iter() {
+            let offset = position - *other_position;
+            let distance = offset.length();
+            if *other == enemy || distance > settings.neighbor_radius {
+                continue
+            }
+            neighbors += 1;
+            heading += *other_velocity;
+            center += *other_position;
+            if distance > 0. && distance < settings.separation_radius {
+                // the closer the neighbor, the harder the push
+                separation += offset / distance * (1. - distance / settings.separation_radius);
+            }
+        }
+        let mut steering = separation * settings.separation;
+        if neighbors > 0 {
+            let count = neighbors as f32;
+            steering += (heading / count - velocity).normalize_or_zero() * settings.alignment;
+            steering += (center / count - position).normalize_or_zero() * settings.cohesion;
+        }
+
+        if *behavior == Behavior::Chasing {
+            let target = nearest_player(player_query.iter().map(|transform| (transform, ())), transform.translation)
+                .map(|(transform, _)| transform.translation.truncate());
+            let spot = match (role, target) {
+                (Role::Flank { right }, Some(target)) => {
+                    Some(target + Vec2::new(if *right { settings.flank_offset } else { -settings.flank_offset }, 0.))
+                }
+                // in front of the gap, on the side the player is on
+                (Role::Guard(gap), Some(target)) => {
+                    Some(*gap + (target - *gap).normalize_or_zero() * settings.guard_distance)
+                }
+                _ => None,
+            };
+            if let Some(spot) = spot {
+                let to_spot = (spot - position).normalize_or_zero() * settings.role;
+                input_influence.0 = to_spot.x;
+                input_influence.1 = to_spot.y;
+            }
+        }
+        input_influence.0 = (input_influence.0 + steering.x).clamp(-1., 1.);
+        input_influence.1 = (input_influence.1 + steering.y).clamp(-1., 1.);
+
+        // this is the last say on the steering, so nothing walks out through a hole in the walls
+        if heads_into_gap(&grid, position, Vec2::new(input_influence.0, 0.)) {
+            input_influence.0 = 0.;
+        }
+        if heads_into_gap(&grid, position, Vec2::new(0., input_influence.1)) {
+            input_influence.1 = 0.;
+        }
     }
 }
 
-pub fn handle_ai_input(
+// how well `attack` suits the moment, for an enemy at `position` going after `target`
+fn score_move(attack: &MoveDefinition, position: Vec2, target: Vec2, target_activity: &Activity) -> f32 {
+    let (collider, center) = attack.hitbox.placed(position, target.x >= position.x);
+    let gap = (target - center).length() - collider.bounds().max_element() - PLAYER_RADIUS;
+    let mut score = if gap <= 0. { 1. } else { (1. - gap / ATTACK_RANGE).max(0.) };
+    // quick moves land before the target can answer them
+    score += 0.3 * (1. - attack.startup as f32 / 20.).max(0.);
+    if attack.projectile.is_some() && gap > 0. {
+        score += 0.5;
+    }
+    match target_activity {
+        // shields don't stop grabs
+        Activity::Shield => score += if attack.throw.is_some() { 1. } else { -0.5 },
+        Activity::Attack(_) if attack.counter => score += 1.,
+        _ if attack.counter => score -= 0.5,
+        _ => {}
+    }
+    score
+}
+
+pub fn handle_ai_input<A: Component, T: Component>(
     player_query: Query<
-        &Transform,
-        (With<Player>, Without<Enemy>),
+        (&Transform, &Activity),
+        (With<T>, Without<A>),
     >,
     mut ai_query: Query<
-        (&mut InputInfluence, &mut Activity, &Behavior, &Transform, &Velocity, &JumpCounter),
-        (With<Enemy>, Without<Player>),
+        (
+            &mut InputInfluence,
+            &mut Activity,
+            &Behavior,
+            &Transform,
+            &Velocity,
+            &JumpCounter,
+            &DodgeCounter,
+            &Shield,
+            Option<&Launched>,
+            Option<&AiPlayer>,
+        ),
+        (With<A>, Without<T>),
     >,
+    grid: Res<BrickGrid>,
+    level: Res<ActiveLevel>,
+    move_set: Res<ActiveMoveSet>,
+    difficulty: Res<AiDifficulty>,
+    tuning: Res<AiSettingsOverride>,
+    mut rng: ResMut<MatchRng>,
 ) {
-    if player_query.get_single().is_err() {
-        return
-    }
-    let player_transform = player_query.get_single().unwrap();
+    let enemy_settings = tuning.settings(&difficulty);
     for (
         mut input_influence,
         mut activity,
@@ -97,11 +625,56 @@ pub fn handle_ai_input(
         transform,
         velocity,
         jumps,
+        dodges,
+        shield,
+        launched,
+        ai_player,
     ) in ai_query.iter_mut() {
+        let settings = ai_player.map_or(enemy_settings, |ai_player| ai_player.0);
         let can_execute_action = *activity == Activity::Idle;
-        let can_jump = jumps.0 > 0 && match activity.as_ref() {
-            Activity::Jump | Activity::Flinch | Activity::Land(_) => false,
-            _ => true,
+        let can_jump = can_jump(activity.as_ref(), jumps);
+        let position = transform.translation.truncate();
+
+        if *behavior == Behavior::Recovering {
+            let definition = match level.definition.as_ref() {
+                Some(definition) => definition,
+                None => continue,
+            };
+            // inside the walls, the middle of the arena is the safest place to
+            // drift, which also turns the launch and checks the momentum once
+            // hitstun is over; outside, any wall or platform to land on will do
+            let center = (definition.arena.min + definition.arena.max) / 2.;
+            let is_outside = is_past_walls(definition, position);
+            let target = if is_outside {
+                grid.nearest_solid(position, RECOVERY_SEARCH).unwrap_or(center)
+            } else {
+                center
+            };
+            let to_target = target - position;
+            let direction = to_target.normalize_or_zero();
+            input_influence.0 = direction.x;
+            input_influence.1 = direction.y;
+
+            // jumps and the dodge are spent only once hitstun is over and the
+            // enemy is outside, and the last jump is saved for when it's falling
+            let is_free = launched.is_none() && *activity != Activity::Flinch && *activity != Activity::Held;
+            let is_below = to_target.y > CELL_SIZE;
+            let is_falling = velocity.1 < 0.;
+            if is_free && is_outside {
+                if can_jump && is_below && (is_falling || jumps.0 > 1) {
+                    *activity = Activity::Jump;
+                } else if !can_jump && is_falling && dodges.0 > 0
+                    && (can_execute_action || *activity == Activity::Jump) {
+                    // dodges towards the target, along the influence set above
+                    *activity = Activity::Dodge;
+                }
+            }
+            continue
+        }
+
+        let (player_transform, player_activity) = match nearest_player(player_query.iter(), transform.translation) {
+            Some(player) => player,
+            None => return,
         };
         let me_to_player = transform.translation - player_transform.translation;
         let me_to_player = Vec2::new(me_to_player.x, me_to_player.y);
@@ -110,25 +683,61 @@ pub fn handle_ai_input(
             Behavior::Chasing => {
                 input_influence.0 = if me_to_player.x.is_sign_positive() { -1. } else { 1. };
                 input_influence.1 = if me_to_player.y.is_sign_positive() { -1. } else { 1. };
-                if me_to_player.y >= 70. && me_to_player.length() < 150. && can_jump {
+                // one jump is always kept back in case of being knocked away
+                if me_to_player.y >= 70. && me_to_player.length() < 150. && can_jump && jumps.0 > 1 {
                     *activity = Activity::Jump;
                 }
             }
             Behavior::Attacking => {
-                if can_execute_action {
-                    if me_to_player.x.is_sign_positive() && me_to_player.x > me_to_player.y.abs() {
-                        *activity = Activity::Counter;
-                    } else if me_to_player.x.is_sign_negative() && -me_to_player.x > me_to_player.y.abs() {
-                        *activity = Activity::Stomp;
+                // drift towards the player, which also turns to face it
+                input_influence.0 = if me_to_player.x.is_sign_positive() { -0.5 } else { 0.5 };
+                input_influence.1 = 0.;
+                if can_execute_action && move_set.len() > 0 {
+                    let target = player_transform.translation.truncate();
+                    // every so often, take a swing that doesn't fit the situation
+                    let is_wild_swing = rng.0.gen_bool(settings.mistake_rate);
+                    let attack = if is_wild_swing {
+                        Some(MoveId(rng.0.gen_range(0..move_set.len())))
                     } else {
-                        *activity = Activity::Jab;
+                        (0..move_set.len())
+                            .map(MoveId)
+                            .filter_map(|id| move_set.get(id)
+                                .map(|attack| (id, score_move(attack, position, target, player_activity))))
+                            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                            .map(|(id, _)| id)
+                    };
+                    if let Some(id) = attack {
+                        *activity = Activity::Attack(id);
                     }
                 }
             }
+            Behavior::Recovering => {}
             Behavior::Evading => {
-                input_influence.0 = if me_to_player.x.is_sign_positive() { 1. } else { -1. };
-                input_influence.1 = if me_to_player.y.is_sign_positive() { 1. } else { -1. };
-                if me_to_player.y <= 30. && me_to_player.length() < 80. && can_jump {
+                // back away from the player, and away from the edge when it's close
+                let away = Vec2::new(
+                    if me_to_player.x.is_sign_positive() { 1. } else { -1. },
+                    if me_to_player.y.is_sign_positive() { 1. } else { -1. },
+                );
+                let danger = edge_danger(&level, position);
+                let to_center = level.definition.as_ref()
+                    .map(|definition| ((definition.arena.min + definition.arena.max) / 2. - position).normalize_or_zero())
+                    .unwrap_or(Vec2::ZERO);
+                let retreat_direction = away.lerp(to_center, danger);
+                input_influence.0 = retreat_direction.x;
+                input_influence.1 = retreat_direction.y;
+                // backed up against a wall, the only way out is up
+                let retreat = Vec2::new(transform.translation.x + input_influence.0.signum() * CELL_SIZE * 2., transform.translation.y);
+                let is_cornered = !grid.query_around(retreat, CELL_SIZE).is_empty();
+                let is_threatened = matches!(player_activity, Activity::Attack(_))
+                    && me_to_player.length() < DEFEND_RANGE
+                    // sometimes the attack isn't seen coming at all
+                    && !rng.0.gen_bool(settings.mistake_rate);
+                if is_threatened && can_execute_action && shield.0 > SHIELD_MAX / 3. {
+                    *activity = Activity::Shield;
+                } else if is_threatened && (can_execute_action || *activity == Activity::Jump) && dodges.0 > 0 {
+                    // dodges away, along the influence set above
+                    *activity = Activity::Dodge;
+                } else if (is_cornered || me_to_player.y <= 30.) && me_to_player.length() < 80. && can_jump && jumps.0 > 1 {
                     *activity = Activity::Jump;
                 }
             }
@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+use std::ops::Range;
+
+use crate::game::{
+    physics::Collider,
+    player::{
+        moves::{MoveDefinition, MoveId},
+        Activity,
+        ActivityFrame,
+        PLAYER_RADIUS,
+    },
+};
+
+// Attacks don't hit with the attacker's body. A move puts out a `Hitbox`, its
+// own entity, which can only hit during its active frames, and only hits each
+// target once. Characters can only be hit where they have a `Hurtbox`.
+
+/// The part of a character that attacks can land on, relative to its center.
+#[derive(Component)]
+pub struct Hurtbox(pub Collider);
+
+impl Default for Hurtbox {
+    fn default() -> Self {
+        Hurtbox(Collider::Ball(PLAYER_RADIUS))
+    }
+}
+
+#[derive(Component)]
+pub struct Hitbox {
+    pub owner: Entity,
+    /// already turned the way the owner was facing
+    pub collider: Collider,
+    /// the frames the hitbox can hit on, counting the step its move started on as frame 1
+    pub active: Range<u32>,
+    /// the hitbox's current frame
+    pub frame: u32,
+    /// when hitboxes meet, the lower priority one is cancelled, or both are if they tie
+    pub priority: i32,
+    /// the direction targets are launched in
+    pub launch: Vec2,
+    pub damage: f32,
+    pub base_knockback: f32,
+    pub knockback_growth: f32,
+    /// in radians
+    pub influence_angle: f32,
+    pub momentum_cancel: f32,
+    /// for grabs, the move that holds the target instead of hitting it
+    pub grab: Option<MoveId>,
+    /// characters the hitbox has already hit
+    pub hit: Vec<Entity>,
+}
+
+impl Hitbox {
+    pub fn is_active(&self) -> bool {
+        self.active.contains(&self.frame)
+    }
+}
+
+/// A hitbox that moves with its owner, for as long as the owner keeps
+/// performing the move it came from.
+#[derive(Component)]
+pub struct Attached {
+    pub offset: Vec2,
+    pub move_id: MoveId,
+}
+
+pub fn spawn_move_hitbox(
+    commands: &mut Commands,
+    owner: Entity,
+    move_id: MoveId,
+    attack: &MoveDefinition,
+    position: Vec3,
+    facing_right: bool,
+) {
+    let (collider, offset) = attack.hitbox.placed(Vec2::ZERO, facing_right);
+    commands.spawn()
+        .insert(Hitbox {
+            owner,
+            collider,
+            active: attack.startup + 1..attack.startup + attack.active + 1,
+            frame: 0,
+            priority: attack.priority,
+            launch: attack.launch_direction(facing_right),
+            damage: attack.damage,
+            base_knockback: attack.base_knockback,
+            knockback_growth: attack.knockback_growth,
+            influence_angle: attack.influence_angle.to_radians(),
+            momentum_cancel: attack.momentum_cancel,
+            grab: attack.throw.as_ref().map(|_| move_id),
+            hit: Vec::new(),
+        })
+        .insert(Attached { offset, move_id })
+        .insert(Transform::from_translation(position + offset.extend(0.)));
+}
+
+/// Keeps attached hitboxes on their owners, and clears them away once the
+/// owner's move is over or interrupted.
+pub fn handle_hitbox_lifetime(
+    mut commands: Commands,
+    mut hitbox_query: Query<(Entity, &mut Hitbox, &Attached, &mut Transform)>,
+    owner_query: Query<(&Activity, &ActivityFrame, &Transform), Without<Hitbox>>,
+) {
+    for (entity, mut hitbox, attached, mut transform) in hitbox_query.iter_mut() {
+        match owner_query.get(hitbox.owner) {
+            Ok((activity, frame, owner_transform))
+                if *activity == Activity::Attack(attached.move_id)
+                    // a move started over again gets a fresh hitbox
+                    && frame.0 >= hitbox.frame
+                    && frame.0 < hitbox.active.end => {
+                hitbox.frame = frame.0;
+                transform.translation = owner_transform.translation + attached.offset.extend(0.);
+            }
+            _ => {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
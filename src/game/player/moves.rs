@@ -0,0 +1,291 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::game::{input::ActionType, physics::Collider, player::TIME_STEP};
+
+// Attacks are described by a move set file rather than in code, so they can be
+// balanced without a rebuild. Frame data is counted in simulation steps, and
+// everything positional is given for a character facing right; it's mirrored
+// for characters facing left.
+
+pub const DEFAULT_MOVE_SET: &str = "moves/default.moves.ron";
+
+/// A move, by its position in the move set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MoveId(pub usize);
+
+/// The regular polygon a character's body is drawn as during a move.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Pose {
+    pub sides: usize,
+    pub apothem: f32,
+}
+
+/// Where a move hits, relative to the center of the character using it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HitShape {
+    #[serde(default)]
+    pub offset: Vec2,
+    pub collider: Collider,
+}
+
+impl HitShape {
+    /// the collider and its center in the world, for a character at `position`
+    pub fn placed(&self, position: Vec2, facing_right: bool) -> (Collider, Vec2) {
+        if facing_right {
+            return (self.collider.clone(), position + self.offset)
+        }
+        (self.collider.mirrored(), position + Vec2::new(-self.offset.x, self.offset.y))
+    }
+}
+
+// a unit vector `degrees` above the direction a character is facing
+fn facing_direction(degrees: f32, facing_right: bool) -> Vec2 {
+    let angle = degrees.to_radians();
+    Vec2::new(
+        if facing_right { angle.cos() } else { -angle.cos() },
+        angle.sin(),
+    )
+}
+
+/// What a grab does with the character it catches.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Throw {
+    /// frames the target is held for before it's thrown
+    pub hold: u32,
+    /// degrees above the direction the thrower is facing that the target is sent
+    pub launch_angle: f32,
+    #[serde(default)]
+    pub damage: f32,
+    pub base_knockback: f32,
+    #[serde(default)]
+    pub knockback_growth: f32,
+}
+
+impl Throw {
+    /// how long the target is held, for the `ActivityTimer`
+    pub fn hold_duration(&self) -> f32 {
+        self.hold as f32 * TIME_STEP
+    }
+
+    pub fn launch_direction(&self, facing_right: bool) -> Vec2 {
+        facing_direction(self.launch_angle, facing_right)
+    }
+}
+
+/// Something a move fires on its first active frame, which flies on its own
+/// and hits like the move's hitbox would.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProjectileDefinition {
+    /// where it's fired from, relative to the center of the character
+    #[serde(default)]
+    pub offset: Vec2,
+    pub speed: f32,
+    /// degrees above the direction the character is facing that it's fired
+    #[serde(default)]
+    pub angle: f32,
+    /// frames before it disappears
+    pub lifetime: u32,
+    pub collider: Collider,
+    #[serde(default)]
+    pub priority: i32,
+    pub launch_angle: f32,
+    #[serde(default)]
+    pub damage: f32,
+    pub base_knockback: f32,
+    #[serde(default)]
+    pub knockback_growth: f32,
+    #[serde(default = "default_influence_angle")]
+    pub influence_angle: f32,
+    #[serde(default = "default_momentum_cancel")]
+    pub momentum_cancel: f32,
+    /// wear dealt to a brick it flies into
+    #[serde(default)]
+    pub brick_damage: f32,
+    pub pose: Pose,
+}
+
+impl ProjectileDefinition {
+    pub fn velocity(&self, facing_right: bool) -> Vec2 {
+        facing_direction(self.angle, facing_right) * self.speed
+    }
+
+    pub fn launch_direction(&self, facing_right: bool) -> Vec2 {
+        facing_direction(self.launch_angle, facing_right)
+    }
+}
+
+fn default_mobility() -> f32 {
+    0.5
+}
+
+fn default_influence_angle() -> f32 {
+    15.
+}
+
+fn default_momentum_cancel() -> f32 {
+    0.3
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MoveDefinition {
+    pub name: String,
+    /// the attack button that performs the move
+    pub action: ActionType,
+    /// frames before the hitbox comes out
+    pub startup: u32,
+    /// frames the hitbox is out for
+    pub active: u32,
+    /// frames after the hitbox is gone before the character can act again
+    pub recovery: u32,
+    pub hitbox: HitShape,
+    /// decides which hitbox wins when two meet, see `Hitbox`
+    #[serde(default)]
+    pub priority: i32,
+    /// degrees above the direction the attacker is facing that targets are sent
+    pub launch_angle: f32,
+    /// damage added to the target, in percent
+    #[serde(default)]
+    pub damage: f32,
+    /// launch speed against a target with no damage
+    pub base_knockback: f32,
+    /// launch speed added for every percent of damage the target has
+    #[serde(default)]
+    pub knockback_growth: f32,
+    /// the most, in degrees, a target can turn its launch with its stick
+    #[serde(default = "default_influence_angle")]
+    pub influence_angle: f32,
+    /// the share of a target's speed it sheds when its hitstun ends, doubled
+    /// if it is holding against its flight
+    #[serde(default = "default_momentum_cancel")]
+    pub momentum_cancel: f32,
+    /// pitch of the attack sound, in steps from middle C
+    pub audio_offset: f32,
+    /// how much of the character's steering is kept during the move
+    #[serde(default = "default_mobility")]
+    pub mobility: f32,
+    /// while active, attacks landing on the character are turned back on the attacker
+    #[serde(default)]
+    pub counter: bool,
+    /// makes the move a grab: instead of hitting, it holds the first character
+    /// it touches and throws it. Grabs go through shields and counters
+    #[serde(default)]
+    pub throw: Option<Throw>,
+    #[serde(default)]
+    pub projectile: Option<ProjectileDefinition>,
+    pub pose: Pose,
+}
+
+impl MoveDefinition {
+    pub fn total_frames(&self) -> u32 {
+        self.startup + self.active + self.recovery
+    }
+
+    /// the move's duration, for the `ActivityTimer`
+    pub fn duration(&self) -> f32 {
+        self.total_frames() as f32 * TIME_STEP
+    }
+
+    /// whether the hitbox is out on `frame`, counting the step the move starts on as frame 1
+    pub fn is_active(&self, frame: u32) -> bool {
+        frame > self.startup && frame <= self.startup + self.active
+    }
+
+    /// the direction the move launches targets in
+    pub fn launch_direction(&self, facing_right: bool) -> Vec2 {
+        facing_direction(self.launch_angle, facing_right)
+    }
+}
+
+/// Every move characters can use, loaded from a `.moves.ron` file.
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "c0a7a4a5-3f4e-4f4c-9a34-1d5f0d8e2b61"]
+pub struct MoveSet {
+    pub moves: Vec<MoveDefinition>,
+}
+
+#[derive(Default)]
+pub struct MoveSetLoader;
+
+impl AssetLoader for MoveSetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let move_set: MoveSet = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(move_set));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["moves.ron"]
+    }
+}
+
+/// The move set of the match in progress. Like the level, the simulation
+/// waits for it to load before starting.
+#[derive(Default)]
+pub struct ActiveMoveSet {
+    pub handle: Handle<MoveSet>,
+    pub definition: Option<MoveSet>,
+}
+
+impl ActiveMoveSet {
+    pub fn is_loaded(&self) -> bool {
+        self.definition.is_some()
+    }
+
+    pub fn get(&self, id: MoveId) -> Option<&MoveDefinition> {
+        self.definition.as_ref()?.moves.get(id.0)
+    }
+
+    /// the first move performed with `action`
+    pub fn for_action(&self, action: &ActionType) -> Option<MoveId> {
+        self.definition.as_ref()?.moves.iter()
+            .position(|definition| &definition.action == action)
+            .map(MoveId)
+    }
+
+    pub fn len(&self) -> usize {
+        self.definition.as_ref().map_or(0, |definition| definition.moves.len())
+    }
+}
+
+pub fn setup(
+    mut move_set: ResMut<ActiveMoveSet>,
+    asset_server: Res<AssetServer>,
+) {
+    *move_set = ActiveMoveSet {
+        handle: asset_server.load(DEFAULT_MOVE_SET),
+        definition: None,
+    };
+}
+
+/// Picks up the move set once it has loaded, and again whenever the file changes.
+pub fn handle_move_set_load(
+    mut move_set: ResMut<ActiveMoveSet>,
+    mut asset_events: EventReader<AssetEvent<MoveSet>>,
+    move_sets: Res<Assets<MoveSet>>,
+) {
+    let was_modified = asset_events.iter().any(|event| match event {
+        AssetEvent::Modified { handle } => handle == &move_set.handle,
+        _ => false,
+    });
+    if move_set.is_loaded() && !was_modified {
+        return
+    }
+    if let Some(definition) = move_sets.get(&move_set.handle) {
+        if was_modified {
+            info!("reloading move set");
+        }
+        move_set.definition = Some(definition.clone());
+    }
+}
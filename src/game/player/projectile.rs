@@ -0,0 +1,131 @@
+use bevy::prelude::*;
+use std::ops::Range;
+
+use crate::game::{
+    geometry,
+    level::{BrickGrid, Durability},
+    physics::{Collider, FixedBody, OneWay, Velocity},
+    player::{
+        hitbox::Hitbox,
+        moves::{ActiveMoveSet, Pose, ProjectileDefinition},
+        Activity,
+        ActivityFrame,
+        Facing,
+        HitStop,
+        TIME_STEP,
+    },
+};
+
+// A projectile is a hitbox that has left its owner: it flies along its own
+// `Velocity` until its lifetime runs out, it hits a character, or it flies
+// into a wall. Hits on characters go through the attack system like any other
+// hitbox; a countered projectile is turned back on whoever fired it.
+
+#[derive(Component)]
+pub struct Projectile {
+    /// steps left before it disappears
+    pub lifetime: u32,
+    /// wear dealt to a brick it flies into
+    pub brick_damage: f32,
+    /// the polygon it's drawn as
+    pub pose: Pose,
+}
+
+// a projectile's hitbox is out for as long as the projectile is
+const ALWAYS_ACTIVE: Range<u32> = 0..u32::MAX;
+
+pub fn spawn_projectile(
+    commands: &mut Commands,
+    owner: Entity,
+    definition: &ProjectileDefinition,
+    position: Vec3,
+    facing_right: bool,
+) {
+    let offset = if facing_right { definition.offset } else { Vec2::new(-definition.offset.x, definition.offset.y) };
+    let velocity = definition.velocity(facing_right);
+    commands.spawn()
+        .insert(Projectile {
+            lifetime: definition.lifetime,
+            brick_damage: definition.brick_damage,
+            pose: definition.pose.clone(),
+        })
+        .insert(Hitbox {
+            owner,
+            collider: definition.collider.clone(),
+            active: ALWAYS_ACTIVE,
+            frame: 0,
+            priority: definition.priority,
+            launch: definition.launch_direction(facing_right),
+            damage: definition.damage,
+            base_knockback: definition.base_knockback,
+            knockback_growth: definition.knockback_growth,
+            influence_angle: definition.influence_angle.to_radians(),
+            momentum_cancel: definition.momentum_cancel,
+            grab: None,
+            hit: Vec::new(),
+        })
+        .insert(Velocity(velocity.x, velocity.y))
+        .insert(Transform::from_translation(position + offset.extend(0.)));
+}
+
+/// Fires the projectiles of moves reaching their first active frame.
+pub fn handle_projectile_spawn(
+    mut commands: Commands,
+    query: Query<(Entity, &Activity, &ActivityFrame, &Transform, &Facing), Without<HitStop>>,
+    move_set: Res<ActiveMoveSet>,
+) {
+    for (character, activity, frame, transform, facing) in query.iter() {
+        let attack = match activity {
+            Activity::Attack(id) => match move_set.get(*id) {
+                Some(attack) => attack,
+                None => continue,
+            },
+            _ => continue,
+        };
+        if let Some(definition) = attack.projectile.as_ref() {
+            if frame.0 == attack.startup + 1 {
+                spawn_projectile(&mut commands, character, definition, transform.translation, facing.is_right());
+            }
+        }
+    }
+}
+
+/// Flies projectiles along, wearing down the bricks they run into, and clears
+/// them away once they've hit something or their time is up.
+pub fn handle_projectiles(
+    mut commands: Commands,
+    mut projectile_query: Query<(Entity, &mut Projectile, &Hitbox, &Velocity, &mut Transform)>,
+    mut fixed_query: Query<
+        (&Collider, &Transform, Option<&mut Durability>),
+        (With<FixedBody>, Without<OneWay>, Without<Projectile>),
+    >,
+    grid: Res<BrickGrid>,
+) {
+    for (entity, mut projectile, hitbox, velocity, mut transform) in projectile_query.iter_mut() {
+        if projectile.lifetime == 0 || !hitbox.hit.is_empty() {
+            commands.entity(entity).despawn_recursive();
+            continue
+        }
+        projectile.lifetime -= 1;
+        transform.translation.x += velocity.0 * TIME_STEP;
+        transform.translation.y += velocity.1 * TIME_STEP;
+
+        let position = transform.translation.truncate();
+        let reach = hitbox.collider.bounds().max_element();
+        for other in grid.query_around(position, reach) {
+            if let Ok((collider, other_transform, durability)) = fixed_query.get_mut(other) {
+                let is_touching = geometry::overlaps(
+                    (&hitbox.collider, position),
+                    (collider, other_transform.translation.truncate()),
+                );
+                if is_touching {
+                    if let Some(mut durability) = durability {
+                        durability.0 -= projectile.brick_damage;
+                    }
+                    commands.entity(entity).despawn_recursive();
+                    break
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,403 @@
+use bevy::prelude::*;
+use bevy_prototype_lyon::{entity::{Path, ShapeBundle}, prelude::*};
+
+use crate::game::{
+    collapse::CollapseWarning,
+    level::{BrickMaterial, BrickType, Durability, Platform, Regrowing, BRICK_APOTHEM},
+    physics::{Collider, OneWay},
+    player::{
+        hitbox::{Hitbox, Hurtbox},
+        moves::ActiveMoveSet,
+        projectile::Projectile,
+        Activity,
+        Combo,
+        Direction,
+        Hype,
+        Player,
+        MAX_PLAYERS,
+        PLAYER_RADIUS,
+    },
+};
+
+// the simulation only spawns plain components; everything drawn on screen is
+// attached here, once the entity has been spawned by the simulation
+
+const PLAYER_COLORS: [Color; MAX_PLAYERS] = [Color::CYAN, Color::YELLOW, Color::LIME_GREEN, Color::VIOLET];
+
+/// The colour each local player is drawn in, by slot.
+pub fn player_color(slot: usize) -> Color {
+    PLAYER_COLORS[slot % MAX_PLAYERS]
+}
+
+fn build_path<T: Geometry>(shape: &T) -> Path {
+    GeometryBuilder::build_as(
+        shape,
+        DrawMode::Fill(FillMode::color(Color::GRAY)),
+        Transform::default(),
+    ).path
+}
+
+fn activity_path(activity: &Activity, move_set: &ActiveMoveSet) -> Path {
+    match activity {
+        Activity::Idle => build_path(&shapes::Circle {
+            radius: PLAYER_RADIUS,
+            ..Default::default()
+        }),
+        Activity::Flinch => build_path(&shapes::Circle {
+            radius: PLAYER_RADIUS - 2.,
+            ..Default::default()
+        }),
+        Activity::Land(direction) => {
+            let radii = if direction == &Direction::Top || direction == &Direction::Bottom {
+                Vec2::new(PLAYER_RADIUS, PLAYER_RADIUS - 5.)
+            } else {
+                Vec2::new(PLAYER_RADIUS - 5., PLAYER_RADIUS)
+            };
+            build_path(&shapes::Ellipse {
+                radii,
+                ..Default::default()
+            })
+        }
+        Activity::Jump => build_path(&shapes::Ellipse {
+            radii: Vec2::new(PLAYER_RADIUS - 5., PLAYER_RADIUS),
+            ..Default::default()
+        }),
+        Activity::Attack(id) => match move_set.get(*id) {
+            Some(attack) => build_path(&shapes::RegularPolygon {
+                sides: attack.pose.sides,
+                feature: shapes::RegularPolygonFeature::Apothem(attack.pose.apothem),
+                ..Default::default()
+            }),
+            None => build_path(&shapes::Circle {
+                radius: PLAYER_RADIUS,
+                ..Default::default()
+            }),
+        },
+        Activity::Shield => build_path(&shapes::Circle {
+            radius: PLAYER_RADIUS + 6.,
+            ..Default::default()
+        }),
+        Activity::ShieldBreak => build_path(&shapes::Ellipse {
+            radii: Vec2::new(PLAYER_RADIUS + 5., PLAYER_RADIUS - 10.),
+            ..Default::default()
+        }),
+        Activity::Dodge => build_path(&shapes::Circle {
+            radius: PLAYER_RADIUS - 8.,
+            ..Default::default()
+        }),
+        Activity::Holding(_) => build_path(&grip_shape()),
+        Activity::Held => build_path(&shapes::Ellipse {
+            radii: Vec2::new(PLAYER_RADIUS - 8., PLAYER_RADIUS - 4.),
+            ..Default::default()
+        }),
+    }
+}
+
+// a body closed around the character it's holding; it opens upwards, like the
+// move poses, and is turned to face the held character along with them
+fn grip_shape() -> shapes::Polygon {
+    let outer = PLAYER_RADIUS;
+    let inner = PLAYER_RADIUS / 2.;
+    shapes::Polygon {
+        points: vec![
+            Vec2::new(-outer, outer),
+            Vec2::new(-outer, -outer),
+            Vec2::new(outer, -outer),
+            Vec2::new(outer, outer),
+            Vec2::new(inner, outer),
+            Vec2::new(inner, -inner),
+            Vec2::new(-inner, -inner),
+            Vec2::new(-inner, outer),
+        ],
+        closed: true,
+    }
+}
+
+pub fn attach_character_shapes(
+    mut commands: Commands,
+    query: Query<(Entity, &Activity, &Transform, Option<&Player>), Added<Activity>>,
+    move_set: Res<ActiveMoveSet>,
+) {
+    let shape = shapes::Circle {
+        radius: PLAYER_RADIUS,
+        ..Default::default()
+    };
+    for (character, activity, transform, player) in query.iter() {
+        let fill = player.map_or(Color::ORANGE_RED, |player| player_color(player.0));
+        let mut bundle = GeometryBuilder::build_as(
+            &shape,
+            DrawMode::Outlined {
+                fill_mode: FillMode::color(fill),
+                outline_mode: StrokeMode::new(Color::BLACK, 4.0),
+            },
+            *transform,
+        );
+        bundle.path = activity_path(activity, &move_set);
+        commands.entity(character).insert_bundle(bundle);
+    }
+}
+
+pub fn attach_brick_shapes(
+    mut commands: Commands,
+    query: Query<(Entity, &BrickType, &Transform), Added<BrickType>>,
+) {
+    let brick_shape = shapes::RegularPolygon {
+        sides: 4,
+        feature: shapes::RegularPolygonFeature::Apothem(BRICK_APOTHEM),
+        ..Default::default()
+    };
+    for (brick, brick_type, transform) in query.iter() {
+        commands.entity(brick).insert_bundle(GeometryBuilder::build_as(
+            &brick_shape,
+            brick_draw_mode(&brick_type.material, 0.),
+            *transform,
+        ));
+    }
+}
+
+fn collider_bundle(collider: &Collider, draw_mode: DrawMode, transform: Transform) -> ShapeBundle {
+    let points = match collider {
+        Collider::Ball(radius) => {
+            return GeometryBuilder::build_as(
+                &shapes::Circle {
+                    radius: *radius,
+                    ..Default::default()
+                },
+                draw_mode,
+                transform,
+            )
+        }
+        collider => collider.corners(Vec2::ZERO),
+    };
+    GeometryBuilder::build_as(
+        &shapes::Polygon {
+            points,
+            closed: true,
+        },
+        draw_mode,
+        transform,
+    )
+}
+
+pub fn attach_platform_shapes(
+    mut commands: Commands,
+    query: Query<(Entity, &Collider, &Transform, Option<&OneWay>), Added<Platform>>,
+) {
+    for (platform, collider, transform, one_way) in query.iter() {
+        let draw_mode = if one_way.is_some() {
+            DrawMode::Fill(FillMode::color(Color::GRAY))
+        } else {
+            DrawMode::Outlined {
+                fill_mode: FillMode::color(Color::BLACK),
+                outline_mode: StrokeMode::new(Color::GRAY, 4.0),
+            }
+        };
+        commands.entity(platform).insert_bundle(collider_bundle(collider, draw_mode, *transform));
+    }
+}
+
+/// Whether hitboxes and hurtboxes are drawn over the game. Toggled with F3.
+#[derive(Default)]
+pub struct DebugOverlay(pub bool);
+
+/// The outline of a character's hurtbox, drawn as a child of the character.
+#[derive(Component)]
+pub struct HurtboxOutline;
+
+const HITBOX_ACTIVE_COLOR: Color = Color::RED;
+const HITBOX_INACTIVE_COLOR: Color = Color::rgba(1.0, 0.0, 0.0, 0.3);
+
+pub fn toggle_debug_overlay(
+    keys: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+pub fn attach_hitbox_shapes(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    query: Query<(Entity, &Hitbox, &Transform), Added<Hitbox>>,
+) {
+    for (entity, hitbox, transform) in query.iter() {
+        let mut bundle = collider_bundle(
+            &hitbox.collider,
+            DrawMode::Stroke(StrokeMode::new(HITBOX_INACTIVE_COLOR, 2.0)),
+            *transform,
+        );
+        bundle.visible.is_visible = overlay.0;
+        commands.entity(entity).insert_bundle(bundle);
+    }
+}
+
+pub fn attach_projectile_shapes(
+    mut commands: Commands,
+    query: Query<(Entity, &Projectile), Added<Projectile>>,
+) {
+    for (entity, projectile) in query.iter() {
+        // drawn as a child, since the projectile itself carries its hitbox's outline
+        let bundle = GeometryBuilder::build_as(
+            &shapes::RegularPolygon {
+                sides: projectile.pose.sides,
+                feature: shapes::RegularPolygonFeature::Apothem(projectile.pose.apothem),
+                ..Default::default()
+            },
+            DrawMode::Fill(FillMode::color(Color::GOLD)),
+            Transform::from_xyz(0., 0., -1.),
+        );
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn_bundle(bundle);
+        });
+    }
+}
+
+pub fn attach_hurtbox_shapes(
+    mut commands: Commands,
+    overlay: Res<DebugOverlay>,
+    query: Query<(Entity, &Hurtbox), Added<Hurtbox>>,
+) {
+    for (character, hurtbox) in query.iter() {
+        let mut bundle = collider_bundle(
+            &hurtbox.0,
+            DrawMode::Stroke(StrokeMode::new(Color::LIME_GREEN, 2.0)),
+            Transform::from_xyz(0., 0., 1.),
+        );
+        bundle.visible.is_visible = overlay.0;
+        commands.entity(character).with_children(|parent| {
+            parent.spawn_bundle(bundle).insert(HurtboxOutline);
+        });
+    }
+}
+
+pub fn handle_debug_overlay(
+    overlay: Res<DebugOverlay>,
+    mut hitbox_query: Query<(&Hitbox, &mut DrawMode, &mut Visible)>,
+    mut outline_query: Query<&mut Visible, (With<HurtboxOutline>, Without<Hitbox>)>,
+) {
+    for (hitbox, mut draw_mode, mut visible) in hitbox_query.iter_mut() {
+        visible.is_visible = overlay.0;
+        let color = if hitbox.is_active() { HITBOX_ACTIVE_COLOR } else { HITBOX_INACTIVE_COLOR };
+        *draw_mode = DrawMode::Stroke(StrokeMode::new(color, 2.0));
+    }
+    if overlay.is_changed() {
+        for mut visible in outline_query.iter_mut() {
+            visible.is_visible = overlay.0;
+        }
+    }
+}
+
+pub fn handle_activity_shape(
+    mut query: Query<(&mut Path, &Activity), Changed<Activity>>,
+    move_set: Res<ActiveMoveSet>,
+) {
+    for (mut path, activity) in query.iter_mut() {
+        *path = activity_path(activity, &move_set);
+    }
+}
+
+// the fill and outline of a brick that hasn't been worn down yet
+fn brick_palette(material: &BrickMaterial) -> (Color, Color) {
+    match material {
+        BrickMaterial::Standard => (Color::BLACK, Color::MAROON),
+        BrickMaterial::Bouncy { .. } => (Color::rgb(0.0, 0.06, 0.08), Color::rgb(0.1, 0.6, 0.5)),
+        BrickMaterial::Unbreakable => (Color::rgb(0.15, 0.15, 0.18), Color::SILVER),
+        BrickMaterial::Explosive { .. } => (Color::rgb(0.1, 0.04, 0.0), Color::ORANGE),
+        BrickMaterial::Regenerating { .. } => (Color::rgb(0.0, 0.06, 0.0), Color::DARK_GREEN),
+    }
+}
+
+fn mix(from: Color, to: Color, amount: f32) -> Color {
+    let (from, to) = (from.as_rgba_f32(), to.as_rgba_f32());
+    Color::rgba(
+        from[0] + (to[0] - from[0]) * amount,
+        from[1] + (to[1] - from[1]) * amount,
+        from[2] + (to[2] - from[2]) * amount,
+        from[3] + (to[3] - from[3]) * amount,
+    )
+}
+
+// how worn a brick looks, in fifths of its durability: bricks above four fifths
+// look new, and the last fifth looks fully worn
+fn brick_wear(durability: f32, max_durability: f32) -> f32 {
+    let lost = (1. - durability / max_durability).clamp(0., 1.);
+    (lost * 5.).floor().min(4.) / 4.
+}
+
+// worn bricks fade towards grey and their outline darkens
+fn brick_draw_mode(material: &BrickMaterial, wear: f32) -> DrawMode {
+    let (fill, outline) = brick_palette(material);
+    DrawMode::Outlined {
+        fill_mode: FillMode::color(mix(fill, Color::rgb(0.2, 0.2, 0.2), wear)),
+        outline_mode: StrokeMode::new(mix(outline, Color::BLACK, 0.46 * wear), 4.0),
+    }
+}
+
+pub fn handle_brick_damage(
+	mut query: Query<(&Durability, &BrickType, &mut DrawMode), (Changed<Durability>, Without<CollapseWarning>)>,
+) {
+	for (durability, brick_type, mut draw_mode) in query.iter_mut() {
+        *draw_mode = brick_draw_mode(&brick_type.material, brick_wear(durability.0, brick_type.durability));
+	}
+}
+
+const COLLAPSE_WARNING_PULSE: f64 = 2.;
+
+/// Bricks about to be affected by the arena collapse pulse through the wear
+/// colours until the stage takes effect.
+pub fn handle_collapse_warning(
+    time: Res<Time>,
+    mut warning_query: Query<(&BrickType, &mut DrawMode), With<CollapseWarning>>,
+    mut brick_query: Query<(&BrickType, Option<&Durability>, &mut DrawMode), Without<CollapseWarning>>,
+    removed: RemovedComponents<CollapseWarning>,
+) {
+    let pulse = (time.seconds_since_startup() * COLLAPSE_WARNING_PULSE).fract() as f32;
+    for (brick_type, mut draw_mode) in warning_query.iter_mut() {
+        *draw_mode = brick_draw_mode(&brick_type.material, pulse);
+    }
+    for brick in removed.iter() {
+        if let Ok((brick_type, durability, mut draw_mode)) = brick_query.get_mut(brick) {
+            let durability = durability.map_or(brick_type.durability, |durability| durability.0);
+            *draw_mode = brick_draw_mode(&brick_type.material, brick_wear(durability, brick_type.durability));
+        }
+    }
+}
+
+/// Broken regenerating bricks are hidden until they grow back.
+pub fn handle_brick_regrowth(
+    mut query: Query<&mut Visible, With<BrickType>>,
+    broken_query: Query<Entity, Added<Regrowing>>,
+    regrown: RemovedComponents<Regrowing>,
+) {
+    for brick in broken_query.iter() {
+        if let Ok(mut visible) = query.get_mut(brick) {
+            visible.is_visible = false;
+        }
+    }
+    for brick in regrown.iter() {
+        if let Ok(mut visible) = query.get_mut(brick) {
+            visible.is_visible = true;
+        }
+    }
+}
+
+pub fn handle_status_change(
+    mut query: Query<
+        (&mut DrawMode, &Hype, &Combo),
+        Or<(Changed<Hype>, Changed<Combo>)>,
+    >,
+) {
+    for (mut draw_mode, hype, combo) in query.iter_mut() {
+        let fill = match *draw_mode {
+            DrawMode::Outlined { fill_mode, outline_mode } => { fill_mode.color }
+            DrawMode::Fill(fill_mode) => { fill_mode.color }
+            DrawMode::Stroke(stroke_mode) => { stroke_mode.color }
+        };
+        let outline = Color::rgb(0.1 + 0.3 * (combo.0 as f32), 0.1, 0.1 + 0.3 * (hype.0 as f32));
+        *draw_mode = DrawMode::Outlined {
+            fill_mode: FillMode::color(fill),
+            outline_mode: StrokeMode::new(outline, 4.0),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use bevy::{ecs::schedule::ShouldRun, prelude::*};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::game::{
+    clock::SimulationClock,
+    input,
+    level::LevelSelection,
+    player::{ai::{AiDifficulty, Difficulty}, PlayerCount},
+    rng::MatchSeed,
+};
+
+/// Bump whenever the replay layout or the meaning of recorded inputs changes.
+pub const REPLAY_VERSION: u32 = 6;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplayEvent {
+    pub tick: u64,
+    pub player: usize,
+    pub trigger: input::Trigger,
+}
+
+/// Everything needed to play a match again: the level, seed, number of players
+/// and enemy difficulty it was generated from and every input it received,
+/// stamped with the simulation tick it arrived on.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub level: String,
+    pub seed: u64,
+    pub players: usize,
+    pub difficulty: Difficulty,
+    pub events: Vec<ReplayEvent>,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Format(ron::Error),
+    UnsupportedVersion(u32),
+}
+
+impl Replay {
+    pub fn new(level: String, seed: u64, players: usize, difficulty: Difficulty) -> Self {
+        Replay {
+            version: REPLAY_VERSION,
+            level,
+            seed,
+            players,
+            difficulty,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ReplayError> {
+        let contents = fs::read_to_string(path).map_err(ReplayError::Io)?;
+        let replay: Replay = ron::de::from_str(&contents).map_err(ReplayError::Format)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::UnsupportedVersion(replay.version))
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(ReplayError::Format)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ReplayError::Io)?;
+        }
+        fs::write(path, contents).map_err(ReplayError::Io)
+    }
+}
+
+/// The replay of the match in progress.
+pub struct ReplayRecorder(pub Replay);
+
+impl Default for ReplayRecorder {
+    fn default() -> Self {
+        ReplayRecorder(Replay::new(LevelSelection::default().0, 0, 1, AiDifficulty::default().0))
+    }
+}
+
+/// Present while a recorded match is being played back; its events take the
+/// place of device input.
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    cursor: usize,
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayback { replay, cursor: 0 }
+    }
+}
+
+/// Finished matches are saved here. Without it, nothing is written to disk.
+pub struct ReplayDirectory(pub PathBuf);
+
+pub fn skip_while_replaying(
+    In(should_run): In<ShouldRun>,
+    playback: Option<Res<ReplayPlayback>>,
+) -> ShouldRun {
+    if playback.is_some() {
+        ShouldRun::No
+    } else {
+        should_run
+    }
+}
+
+pub fn start_recording(
+    mut recorder: ResMut<ReplayRecorder>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    level: Res<LevelSelection>,
+    seed: Res<MatchSeed>,
+    players: Res<PlayerCount>,
+    difficulty: Res<AiDifficulty>,
+) {
+    recorder.0 = Replay::new(level.0.clone(), seed.0, players.0, difficulty.0);
+    if let Some(mut playback) = playback {
+        playback.cursor = 0;
+    }
+}
+
+pub fn record_input_events(
+    mut events: EventReader<input::Event>,
+    mut recorder: ResMut<ReplayRecorder>,
+    clock: Res<SimulationClock>,
+) {
+    for event in events.iter() {
+        recorder.0.events.push(ReplayEvent {
+            tick: clock.tick,
+            player: event.0,
+            trigger: event.1.clone(),
+        });
+    }
+}
+
+pub fn play_replay_events(
+    mut writer: EventWriter<input::Event>,
+    playback: Option<ResMut<ReplayPlayback>>,
+    clock: Res<SimulationClock>,
+) {
+    if let Some(mut playback) = playback {
+        while let Some(event) = playback.replay.events.get(playback.cursor) {
+            if event.tick > clock.tick {
+                break
+            }
+            writer.send(input::Event(event.player, event.trigger.clone()));
+            playback.cursor += 1;
+        }
+    }
+}
+
+pub fn save_replay(
+    recorder: Res<ReplayRecorder>,
+    directory: Option<Res<ReplayDirectory>>,
+    playback: Option<Res<ReplayPlayback>>,
+) {
+    // replays of replays are just copies
+    if playback.is_some() {
+        return
+    }
+    if let Some(directory) = directory {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let path = directory.0.join(format!("{}-{}.ron", timestamp, recorder.0.seed));
+        match recorder.0.save(&path) {
+            Ok(()) => info!("saved replay to {}", path.display()),
+            Err(error) => warn!("failed to save replay to {}: {:?}", path.display(), error),
+        }
+    }
+}
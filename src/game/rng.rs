@@ -0,0 +1,29 @@
+use bevy::prelude::*;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+/// The seed a match is played from. Together with the input stream, it fully
+/// determines the match.
+#[derive(Clone, Copy, Debug)]
+pub struct MatchSeed(pub u64);
+
+impl Default for MatchSeed {
+    fn default() -> Self {
+        MatchSeed(rand::random())
+    }
+}
+
+/// The only source of randomness gameplay systems may draw from.
+/// ChaCha is used because its output is stable across platforms and releases.
+pub struct MatchRng(pub ChaCha8Rng);
+
+impl Default for MatchRng {
+    fn default() -> Self {
+        MatchRng(ChaCha8Rng::seed_from_u64(0))
+    }
+}
+
+pub fn reseed_match_rng(seed: Res<MatchSeed>, mut rng: ResMut<MatchRng>) {
+    info!("match seed: {}", seed.0);
+    rng.0 = ChaCha8Rng::seed_from_u64(seed.0);
+}
@@ -1,6 +1,9 @@
 use bevy::prelude::*;
 
-use crate::game::level::{LEVEL_HEIGHT, LEVEL_WIDTH};
+use crate::game::{
+    player::{Damage, Eliminations, Player, PlayerCount},
+    presentation::player_color,
+};
 
 #[derive(Default)]
 pub struct Score(pub i32);
@@ -9,6 +12,9 @@ pub struct Score(pub i32);
 pub struct ScoreboardRootNode;
 #[derive(Component)]
 pub struct ScoreboardNode;
+/// The damage readout of the local player in this slot.
+#[derive(Component)]
+pub struct DamageNode(pub usize);
 
 const BACKGROUND: Color = Color::rgb(0.1, 0.2, 0.45);
 
@@ -64,6 +70,64 @@ pub fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         });
 }
 
+pub fn setup_damage(mut commands: Commands, asset_server: Res<AssetServer>, players: Res<PlayerCount>) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let mut style = Style::default();
+    style.position_type = PositionType::Absolute;
+    // one readout per player, side by side
+    style.justify_content = JustifyContent::SpaceAround;
+    style.position = Rect {
+        top: Val::Percent(0.),
+        left: Val::Percent(100. - 12. * players.0 as f32),
+        right: Val::Percent(0.),
+        bottom: Val::Percent(93.),
+    };
+
+    commands
+        .spawn()
+        .insert(ScoreboardRootNode)
+        .insert_bundle(NodeBundle {
+            color: BACKGROUND.into(),
+            style,
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for slot in 0..players.0 {
+                parent
+                    .spawn()
+                    .insert(DamageNode(slot))
+                    .insert_bundle(TextBundle {
+                        text: Text::with_section(
+                            "0%".to_string(),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 40.0,
+                                color: player_color(slot),
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+            }
+        });
+}
+
+pub fn handle_tracking_damage(
+    mut ui_query: Query<(&mut Text, &DamageNode)>,
+    player_query: Query<(&Player, &Damage), Changed<Damage>>,
+    eliminations: Res<Eliminations>,
+) {
+    for (mut text_node, node) in ui_query.iter_mut() {
+        if eliminations.0.contains(&node.0) {
+            if eliminations.is_changed() {
+                text_node.sections[0].value = "KO".to_string();
+            }
+        } else if let Some((_, damage)) = player_query.iter().find(|(player, _)| player.0 == node.0) {
+            text_node.sections[0].value = format!("{:.0}%", damage.0);
+        }
+    }
+}
+
 pub fn handle_tracking_score(
     mut ui_query: Query<&mut Text, With<ScoreboardNode>>,
     res: Res<Score>,
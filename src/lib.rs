@@ -0,0 +1,14 @@
+#![feature(drain_filter)]
+
+pub mod audio;
+pub mod game;
+pub mod utils;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+pub enum AppState {
+    Join,
+    Game,
+    /// pushed over `Game`, which carries on where it left off when this is popped
+    Paused,
+    GameOver,
+}
@@ -1,16 +1,18 @@
-#![feature(drain_filter)]
+use bevy::{asset::AssetServerSettings, prelude::*};
+use std::path::{Path, PathBuf};
 
-use bevy::prelude::*;
-
-pub mod audio;
-pub mod game;
-pub mod utils;
-
-#[derive(Clone, Eq, PartialEq, Debug, Hash)]
-pub enum AppState {
-    Game,
-    GameOver,
-}
+use ballpit_arena_game::{
+    audio,
+    game::{
+        self,
+        bindings::{Bindings, BindingsFile},
+        level::LevelSelection,
+        replay::{Replay, ReplayDirectory, ReplayPlayback},
+        player::{ai::{AiDifficulty, Difficulty}, PlayerCount},
+        rng::MatchSeed,
+    },
+    AppState,
+};
 
 fn setup_ui_camera(mut commands: Commands) {
     // HUD and menu screen
@@ -19,11 +21,64 @@ fn setup_ui_camera(mut commands: Commands) {
     commands.spawn_bundle(OrthographicCameraBundle::new_2d());
 }
 
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
 fn main() {
-    App::new()
+    let mut app = App::new();
+    // players join before a match, unless it's a replay, which already knows who played
+    let mut initial_state = AppState::Join;
+
+    // `--replay <file>` plays back a recorded match instead of reading devices,
+    // `--level <file>` picks a level from the assets folder,
+    // `--seed <number>` replays the same match layout, otherwise a fresh seed is rolled,
+    // `--difficulty <easy|normal|hard>` sets how well enemies play
+    if let Some(path) = arg_value("--replay") {
+        let replay = Replay::load(Path::new(&path))
+            .unwrap_or_else(|error| panic!("could not load replay {}: {:?}", path, error));
+        app.insert_resource(MatchSeed(replay.seed))
+            .insert_resource(LevelSelection(replay.level.clone()))
+            .insert_resource(PlayerCount(replay.players))
+            .insert_resource(AiDifficulty(replay.difficulty))
+            .insert_resource(ReplayPlayback::new(replay));
+        initial_state = AppState::Game;
+    } else {
+        let seed = arg_value("--seed")
+            .and_then(|seed| seed.parse().ok())
+            .map(MatchSeed)
+            .unwrap_or_default();
+        app.insert_resource(seed);
+        if let Some(level) = arg_value("--level") {
+            app.insert_resource(LevelSelection(level));
+        }
+        if let Some(difficulty) = arg_value("--difficulty") {
+            let difficulty: Difficulty = difficulty.parse()
+                .unwrap_or_else(|error| panic!("{}", error));
+            app.insert_resource(AiDifficulty(difficulty));
+        }
+    }
+
+    // bindings changed in the pause menu are saved to the user's config directory
+    if let Some(file) = BindingsFile::in_user_config() {
+        app.insert_resource(Bindings::load_or_default(&file.0))
+            .insert_resource(file);
+    }
+
+    app
+        .insert_resource(ReplayDirectory(PathBuf::from("replays")))
+        // level files are reloaded as they are edited
+        .insert_resource(AssetServerSettings {
+            watch_for_changes: true,
+            ..Default::default()
+        })
         .add_plugins(DefaultPlugins)
         .add_plugin(audio::AudioPlugin)
-        .add_state(AppState::Game)
+        .add_state(initial_state)
         .add_startup_system(setup_ui_camera)
         .add_plugin(game::GamePlugin)
         .run();
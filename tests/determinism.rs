@@ -0,0 +1,106 @@
+use bevy::{asset::AssetPlugin, prelude::*};
+use std::time::{Duration, Instant};
+
+use ballpit_arena_game::{
+    game::{
+        clock::{self, ClockMode, SimulationClock},
+        input::{self, ActionType, Trigger},
+        physics::Velocity,
+        rng::MatchSeed,
+        SimulationPlugin,
+    },
+    AppState,
+};
+
+// The same seed and inputs must move every body through exactly the same
+// positions and velocities, down to the bit, however the executor schedules
+// the simulation's systems.
+
+// long enough for enemies to spawn and fight, which draws on the match RNG
+const STEPS: u64 = 1500;
+const LOAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The position and velocity of every body after each step, as raw bits.
+#[derive(Default)]
+struct Trace(Vec<Vec<(u32, [u32; 4])>>);
+
+// walks the player round in circles, jumping and attacking on a fixed beat
+fn send_scripted_input(clock: Res<SimulationClock>, mut events: EventWriter<input::Event>) {
+    if clock.tick % 10 == 0 {
+        let angle = clock.tick as f32 * 0.02;
+        events.send(input::Event(0, Trigger::PlayerMovement(angle.cos(), angle.sin())));
+    }
+    let action = match clock.tick % 90 {
+        15 => Some(ActionType::Jump),
+        40 => Some(ActionType::Jab),
+        65 => Some(ActionType::Stomp),
+        80 => Some(ActionType::Counter),
+        _ => None,
+    };
+    if let Some(action) = action {
+        events.send(input::Event(0, Trigger::PlayerAction(action)));
+    }
+}
+
+// runs once the whole frame is over, so it sees every step exactly as it ended
+fn record_trace(
+    mut trace: ResMut<Trace>,
+    mut last_tick: Local<u64>,
+    clock: Res<SimulationClock>,
+    query: Query<(Entity, &Transform, &Velocity)>,
+) {
+    if clock.tick == *last_tick {
+        return
+    }
+    *last_tick = clock.tick;
+    let mut step: Vec<(u32, [u32; 4])> = query.iter()
+        .map(|(entity, transform, velocity)| (entity.id(), [
+            transform.translation.x.to_bits(),
+            transform.translation.y.to_bits(),
+            velocity.0.to_bits(),
+            velocity.1.to_bits(),
+        ]))
+        .collect();
+    step.sort_by_key(|(id, _)| *id);
+    trace.0.push(step);
+}
+
+fn run_match(seed: u64) -> Vec<Vec<(u32, [u32; 4])>> {
+    let mut app = App::new();
+    app.insert_resource(MatchSeed(seed))
+        .insert_resource(SimulationClock::new(ClockMode::Uncapped))
+        .init_resource::<Trace>()
+        .add_plugins(MinimalPlugins)
+        .add_plugin(AssetPlugin)
+        .add_state(AppState::Game)
+        .add_plugin(SimulationPlugin)
+        .add_system_set(
+            SystemSet::new()
+                .with_run_criteria(clock::SIMULATION_STEP)
+                .with_system(send_scripted_input.before("handle_input_events"))
+        )
+        .add_system_to_stage(CoreStage::PostUpdate, record_trace);
+
+    let started = Instant::now();
+    loop {
+        app.update();
+        let tick = app.world.get_resource::<SimulationClock>().unwrap().tick;
+        let state = app.world.get_resource::<State<AppState>>().unwrap();
+        if state.current() != &AppState::Game || tick >= STEPS {
+            break
+        }
+        assert!(tick > 0 || started.elapsed() < LOAD_TIMEOUT, "the match never started");
+    }
+    app.world.remove_resource::<Trace>().unwrap().0
+}
+
+#[test]
+fn same_seed_and_inputs_give_identical_traces() {
+    let first = run_match(7);
+    let second = run_match(7);
+    assert!(first.len() > 60, "the match ended after {} steps", first.len());
+    assert_eq!(first.len(), second.len(), "the matches lasted different lengths");
+    for (step, (a, b)) in first.iter().zip(second.iter()).enumerate() {
+        assert_eq!(a, b, "the traces diverged at step {}", step + 1);
+    }
+}